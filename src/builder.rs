@@ -0,0 +1,256 @@
+// Builder to configure the vehicle limits of a pathfinder for a specific airframe
+
+use super::*;
+
+#[derive(Debug)]
+pub struct PathfinderBuilder {
+    buffer: f32,                // In meters
+    max_process_time: Duration, // In seconds
+    turning_radius: f32,        // In meters
+    max_angle_ascent: f32,      // In radians, positive
+    max_angle_descent: f32,     // In radians, negative
+    min_buffer: f32,            // In meters
+    speed: f32,                 // In meters per second
+    parallel: bool,
+    incremental: bool,
+    dubins: bool,
+    repair_waypoints: bool,
+    planner: Option<Box<dyn Planner>>,
+    config: Option<PathfinderConfig>,
+}
+
+impl Default for PathfinderBuilder {
+    fn default() -> Self {
+        PathfinderBuilder {
+            buffer: MIN_BUFFER,
+            max_process_time: Duration::from_secs(10u64),
+            turning_radius: TURNING_RADIUS,
+            max_angle_ascent: MAX_ANGLE_ASCENT,
+            max_angle_descent: MAX_ANGLE_DESCENT,
+            min_buffer: MIN_BUFFER,
+            speed: SPEED,
+            parallel: false,
+            incremental: false,
+            dubins: false,
+            repair_waypoints: false,
+            planner: None,
+            config: None,
+        }
+    }
+}
+
+impl PathfinderBuilder {
+    pub fn new() -> Self {
+        PathfinderBuilder::default()
+    }
+
+    pub fn buffer(mut self, buffer: f32) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    pub fn process_time(mut self, max_process_time: Duration) -> Self {
+        self.max_process_time = max_process_time;
+        self
+    }
+
+    pub fn turning_radius(mut self, turning_radius: f32) -> Self {
+        self.turning_radius = turning_radius;
+        self
+    }
+
+    pub fn max_angle_ascent(mut self, max_angle_ascent: f32) -> Self {
+        self.max_angle_ascent = max_angle_ascent;
+        self
+    }
+
+    pub fn max_angle_descent(mut self, max_angle_descent: f32) -> Self {
+        self.max_angle_descent = max_angle_descent;
+        self
+    }
+
+    pub fn min_buffer(mut self, min_buffer: f32) -> Self {
+        self.min_buffer = min_buffer;
+        self
+    }
+
+    // Cruise speed, used to predict where moving obstacles are when the plane reaches them
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    // Plan legs of a mission on multiple threads
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    // Keep the search of each leg between calls and repair it instead of starting over
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    // Join the points of the path with Dubins paths the plane can fly within its turning radius
+    pub fn dubins(mut self, dubins: bool) -> Self {
+        self.dubins = dubins;
+        self
+    }
+
+    // Move waypoints the plane cannot reach to the nearest location it can
+    pub fn repair_waypoints(mut self, repair_waypoints: bool) -> Self {
+        self.repair_waypoints = repair_waypoints;
+        self
+    }
+
+    // Find paths with planner instead of A* on the tangent graph
+    pub fn planner<P: Planner + 'static>(mut self, planner: P) -> Self {
+        self.planner = Some(Box::new(planner));
+        self
+    }
+
+    // Weights to use instead of the defaults, e.g. PathfinderConfig::load()?
+    pub fn config(mut self, config: PathfinderConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    // Build a pathfinder that still needs to be initialized with flyzones
+    pub fn build(self) -> Result<Pathfinder, PathfinderError> {
+        self.validate()?;
+        let mut pathfinder = Pathfinder::from_config(self.config.unwrap_or_default());
+        pathfinder.buffer = self.buffer.max(self.min_buffer);
+        pathfinder.max_process_time = self.max_process_time;
+        pathfinder.turning_radius = self.turning_radius;
+        pathfinder.max_angle_ascent = self.max_angle_ascent;
+        pathfinder.max_angle_descent = self.max_angle_descent;
+        pathfinder.min_buffer = self.min_buffer;
+        pathfinder.speed = self.speed;
+        pathfinder.parallel = self.parallel;
+        pathfinder.incremental = self.incremental;
+        pathfinder.dubins = self.dubins;
+        pathfinder.repair_waypoints = self.repair_waypoints;
+        if let Some(planner) = self.planner {
+            pathfinder.planner = planner;
+        }
+        Ok(pathfinder)
+    }
+
+    // Build and initialize a pathfinder
+    pub fn create<F: Into<Flyzone>>(
+        self,
+        flyzones: Vec<F>,
+        obstacles: Vec<Obstacle>,
+    ) -> Result<Pathfinder, PathfinderError> {
+        let buffer = self.buffer;
+        let mut pathfinder = self.build()?;
+        pathfinder.try_init(buffer, flyzones, obstacles)?;
+        Ok(pathfinder)
+    }
+
+    fn validate(&self) -> Result<(), PathfinderError> {
+        let invalid =
+            |name: &'static str, value: f32| Err(PathfinderError::InvalidParameter { name, value });
+        if self.buffer.is_nan() || self.buffer < 0f32 {
+            return invalid("buffer", self.buffer);
+        }
+        if self.min_buffer.is_nan() || self.min_buffer < 0f32 {
+            return invalid("min_buffer", self.min_buffer);
+        }
+        if !within(self.turning_radius, 0f32, f32::MAX) || self.turning_radius == 0f32 {
+            return invalid("turning_radius", self.turning_radius);
+        }
+        if !within(self.speed, 0f32, f32::MAX) || self.speed == 0f32 {
+            return invalid("speed", self.speed);
+        }
+        if !within(self.max_angle_ascent, 0f32, PI / 2f32) || self.max_angle_ascent == 0f32 {
+            return invalid("max_angle_ascent", self.max_angle_ascent);
+        }
+        if !within(self.max_angle_descent, -PI / 2f32, 0f32) || self.max_angle_descent == 0f32 {
+            return invalid("max_angle_descent", self.max_angle_descent);
+        }
+        Ok(())
+    }
+}
+
+// true if value is in [min, max], false for NaN
+fn within(value: f32, min: f32, max: f32) -> bool {
+    value >= min && value <= max
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use graph::Point;
+
+    #[test]
+    fn invalid_limits() {
+        assert_eq!(
+            PathfinderBuilder::new().turning_radius(0f32).build().err(),
+            Some(PathfinderError::InvalidParameter {
+                name: "turning_radius",
+                value: 0f32
+            })
+        );
+        assert_eq!(
+            PathfinderBuilder::new().max_angle_ascent(PI).build().err(),
+            Some(PathfinderError::InvalidParameter {
+                name: "max_angle_ascent",
+                value: PI
+            })
+        );
+        assert_eq!(
+            PathfinderBuilder::new()
+                .max_angle_descent(PI / 4f32)
+                .build()
+                .err(),
+            Some(PathfinderError::InvalidParameter {
+                name: "max_angle_descent",
+                value: PI / 4f32
+            })
+        );
+        assert!(PathfinderBuilder::new().buffer(-1f32).build().is_err());
+        assert!(PathfinderBuilder::new().speed(0f32).build().is_err());
+        assert!(PathfinderBuilder::new()
+            .turning_radius(f32::NAN)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn vehicle_limits() {
+        let pathfinder = PathfinderBuilder::new()
+            .buffer(1f32)
+            .min_buffer(2f32)
+            .turning_radius(10f32)
+            .max_angle_ascent(PI / 6f32)
+            .max_angle_descent(-PI / 4f32)
+            .process_time(Duration::from_secs(2))
+            .build()
+            .unwrap();
+        assert_eq!(pathfinder.get_buffer(), 2f32);
+        assert_eq!(pathfinder.get_turning_radius(), 10f32);
+        assert_eq!(pathfinder.get_max_angle_ascent(), PI / 6f32);
+        assert_eq!(pathfinder.get_max_angle_descent(), -PI / 4f32);
+        assert_eq!(pathfinder.get_process_time(), 2);
+    }
+
+    #[test]
+    fn turning_radius_flyzone() {
+        let origin = Location::from_degrees(0f64, 0f64, 0f32);
+        let flyzone = vec![vec![
+            Point::new(0f32, 20f32, 10f32).to_location(&origin),
+            Point::new(20f32, 20f32, 10f32).to_location(&origin),
+            Point::new(20f32, 0f32, 10f32).to_location(&origin),
+            Point::new(0f32, 0f32, 10f32).to_location(&origin),
+        ]];
+        let pathfinder = PathfinderBuilder::new()
+            .turning_radius(2f32)
+            .create(flyzone, Vec::new())
+            .unwrap();
+        let node = &pathfinder.graph.nodes[0];
+        assert!(node.origin.distance(&Point::new(2f32, 18f32, 0f32)) < 0.001);
+        assert_eq!(node.radius, 2f32);
+    }
+}
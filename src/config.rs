@@ -0,0 +1,165 @@
+// Weights used to calculate path preferences, loaded from pathfinder.toml and environment
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use toml::Value;
+
+use error::PathfinderError;
+
+const CONFIG_FILE: &str = "pathfinder.toml";
+const DIRECT_PATH_MODIFIER_WEIGHT: &str = "direct_path_modifier_weight";
+const HEADING_MODIFIER_WEIGHT: &str = "heading_modifier_weight";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathfinderConfig {
+    // high value makes Pathfinder prefer direct paths
+    pub direct_path_modifier_weight: f32,
+    // high value makes Pathfinder prefer paths that maintains current heading
+    pub heading_modifier_weight: f32,
+}
+
+impl Default for PathfinderConfig {
+    fn default() -> Self {
+        PathfinderConfig {
+            direct_path_modifier_weight: 0f32,
+            heading_modifier_weight: 0f32,
+        }
+    }
+}
+
+impl PathfinderConfig {
+    pub fn new() -> Self {
+        PathfinderConfig::default()
+    }
+
+    // Load pathfinder.toml from the working directory if it exists, then apply environment
+    // variables on top
+    pub fn load() -> Result<Self, PathfinderError> {
+        let config = if Path::new(CONFIG_FILE).exists() {
+            PathfinderConfig::from_file(CONFIG_FILE)?
+        } else {
+            PathfinderConfig::default()
+        };
+        config.with_env()
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PathfinderError> {
+        let text =
+            fs::read_to_string(path).map_err(|e| PathfinderError::InvalidConfig(e.to_string()))?;
+        PathfinderConfig::from_toml(&text)
+    }
+
+    // Parse weights from TOML text, given as floats or integers
+    // Fails on a parameter that is present but not a number
+    pub fn from_toml(text: &str) -> Result<Self, PathfinderError> {
+        let table = text
+            .parse::<Value>()
+            .map_err(|e| PathfinderError::InvalidConfig(e.to_string()))?;
+        PathfinderConfig::new().overlay(|key| match table.get(key) {
+            Some(&Value::Float(v)) => Ok(Some(v as f32)),
+            Some(&Value::Integer(v)) => Ok(Some(v as f32)),
+            Some(v) => Err(invalid(key, v)),
+            None => Ok(None),
+        })
+    }
+
+    // Override weights with environment variables named after the uppercase parameter,
+    // e.g. DIRECT_PATH_MODIFIER_WEIGHT
+    // Fails on a variable that is set but not a number
+    pub fn with_env(self) -> Result<Self, PathfinderError> {
+        self.overlay(|key| match env::var(key.to_uppercase()) {
+            Ok(v) => parse_weight(key, &v).map(Some),
+            Err(_) => Ok(None),
+        })
+    }
+
+    fn overlay<F>(mut self, lookup: F) -> Result<Self, PathfinderError>
+    where
+        F: Fn(&str) -> Result<Option<f32>, PathfinderError>,
+    {
+        if let Some(weight) = lookup(DIRECT_PATH_MODIFIER_WEIGHT)? {
+            self.direct_path_modifier_weight = weight;
+        }
+        if let Some(weight) = lookup(HEADING_MODIFIER_WEIGHT)? {
+            self.heading_modifier_weight = weight;
+        }
+        Ok(self)
+    }
+}
+
+fn parse_weight(key: &str, value: &str) -> Result<f32, PathfinderError> {
+    value.trim().parse::<f32>().map_err(|_| invalid(key, value))
+}
+
+fn invalid<V: fmt::Display>(key: &str, value: V) -> PathfinderError {
+    PathfinderError::InvalidConfig(format!("{} is not a number: {}", key, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Pathfinder;
+
+    #[test]
+    fn parse_toml() {
+        let config = PathfinderConfig::from_toml(
+            "direct_path_modifier_weight = 0.5\nheading_modifier_weight = 0.25\n",
+        )
+        .unwrap();
+        assert_eq!(config.direct_path_modifier_weight, 0.5f32);
+        assert_eq!(config.heading_modifier_weight, 0.25f32);
+    }
+
+    #[test]
+    fn integer_and_invalid() {
+        let config = PathfinderConfig::from_toml(
+            "direct_path_modifier_weight = 1\nheading_modifier_weight = 0.25\n",
+        )
+        .unwrap();
+        assert_eq!(config.direct_path_modifier_weight, 1f32);
+        assert_eq!(config.heading_modifier_weight, 0.25f32);
+        assert!(PathfinderConfig::from_toml("direct_path_modifier_weight = ").is_err());
+        assert_eq!(
+            PathfinderConfig::from_toml("heading_modifier_weight = \"high\""),
+            Err(PathfinderError::InvalidConfig(
+                "heading_modifier_weight is not a number: \"high\"".to_string()
+            ))
+        );
+        assert_eq!(parse_weight(HEADING_MODIFIER_WEIGHT, " 2 "), Ok(2f32));
+        assert!(parse_weight(HEADING_MODIFIER_WEIGHT, "2m").is_err());
+    }
+
+    #[test]
+    fn overlay_weights() {
+        let config = PathfinderConfig::from_toml("heading_modifier_weight = 0.25")
+            .unwrap()
+            .overlay(|key| {
+                if key == DIRECT_PATH_MODIFIER_WEIGHT {
+                    Ok(Some(2f32))
+                } else {
+                    Ok(None)
+                }
+            })
+            .unwrap();
+        assert_eq!(config.direct_path_modifier_weight, 2f32);
+        assert_eq!(config.heading_modifier_weight, 0.25f32);
+    }
+
+    #[test]
+    fn shipped_config() {
+        let config = PathfinderConfig::from_file(CONFIG_FILE).unwrap();
+        assert_eq!(config.direct_path_modifier_weight, 0.001f32);
+        assert_eq!(config.heading_modifier_weight, 0f32);
+    }
+
+    #[test]
+    fn explicit_config() {
+        // nothing is read unless asked for
+        assert_eq!(*Pathfinder::new().get_config(), PathfinderConfig::default());
+        let config = PathfinderConfig::from_file(CONFIG_FILE).unwrap();
+        assert_eq!(*Pathfinder::from_config(config).get_config(), config);
+    }
+}
@@ -0,0 +1,461 @@
+// Dubins paths, the shortest ways between two headings for a plane that cannot turn tighter
+// than its turning radius: two arcs joined by a straight line or by a third arc
+// Formulas from Shkel and Lumelsky, "Classification of the Dubins set"
+
+use super::*;
+
+use self::Turn::*;
+
+// Direction of one part of a Dubins path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Turn {
+    Left,
+    Straight,
+    Right,
+}
+
+// The six kinds of Dubins paths
+const WORDS: [[Turn; 3]; 6] = [
+    [Left, Straight, Left],
+    [Right, Straight, Right],
+    [Left, Straight, Right],
+    [Right, Straight, Left],
+    [Right, Left, Right],
+    [Left, Right, Left],
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Dubins {
+    start: Point,
+    end: Point,
+    heading: f32, // In radians counter-clockwise from east
+    radius: f32,  // In meters
+    turns: [Turn; 3],
+    lengths: [f32; 3], // In meters
+}
+
+impl Dubins {
+    // Every Dubins path leaving start at heading and arriving at end at end_heading, shortest
+    // first; altitude changes steadily along the path
+    pub fn candidates(
+        start: &Point,
+        heading: f32,
+        end: &Point,
+        end_heading: f32,
+        radius: f32,
+    ) -> Vec<Dubins> {
+        // headings relative to the line from start to end, distance in turning radii
+        let theta = (end.y - start.y).atan2(end.x - start.x);
+        let a = (heading - theta).rem_euclid(2f32 * PI);
+        let b = (end_heading - theta).rem_euclid(2f32 * PI);
+        let d = start.distance(end) / radius;
+        let mut paths: Vec<Dubins> = WORDS
+            .iter()
+            .filter_map(|turns| {
+                let (t, p, q) = normalized(turns, a, b, d)?;
+                Some(Dubins {
+                    start: *start,
+                    end: *end,
+                    heading: heading,
+                    radius: radius,
+                    turns: *turns,
+                    lengths: [t * radius, p * radius, q * radius],
+                })
+            })
+            .filter(|path| path.length().is_finite())
+            .collect();
+        paths.sort_by(|a, b| a.length().partial_cmp(&b.length()).unwrap());
+        paths
+    }
+
+    pub fn length(&self) -> f32 {
+        self.lengths.iter().sum()
+    }
+
+    pub fn turns(&self) -> [Turn; 3] {
+        self.turns
+    }
+
+    // Location and heading after flying distance s along the path
+    pub fn at(&self, s: f32) -> (Point, f32) {
+        let (mut point, mut heading) = (self.start, self.heading);
+        let mut left = s;
+        for (turn, length) in self.turns.iter().zip(self.lengths.iter()) {
+            let part = left.min(*length);
+            let (next, next_heading) = advance(&point, heading, *turn, part, self.radius);
+            point = next;
+            heading = next_heading;
+            left -= part;
+            if left <= 0f32 {
+                break;
+            }
+        }
+        let rise = if self.length() > 0f32 {
+            (self.end.z - self.start.z) * s.min(self.length()) / self.length()
+        } else {
+            0f32
+        };
+        (Point::new(point.x, point.y, self.start.z + rise), heading)
+    }
+
+    // Points along the path after start, ending exactly at end
+    // Arcs are split so that no two points are more than step radians of turn apart, parts too
+    // short to matter are left out
+    pub fn sample(&self, step: f32) -> Vec<Point> {
+        let mut points: Vec<Point> = Vec::new();
+        let mut s = 0f32;
+        for (turn, length) in self.turns.iter().zip(self.lengths.iter()) {
+            if *length <= 0f32 {
+                continue;
+            }
+            let pieces = match *turn {
+                Straight => 1,
+                _ => (length / self.radius / step).ceil().max(1f32) as usize,
+            };
+            for k in 1..=pieces {
+                let point = self.at(s + length * k as f32 / pieces as f32).0;
+                if point.distance(points.last().unwrap_or(&self.start)) > 0.01 {
+                    points.push(point);
+                }
+            }
+            s += length;
+        }
+        points.pop();
+        points.push(self.end);
+        points
+    }
+}
+
+// Lengths of the three parts of the path with turns, in turning radii, from heading a to
+// heading b relative to the line between ends d turning radii apart; none if impossible
+fn normalized(turns: &[Turn; 3], a: f32, b: f32, d: f32) -> Option<(f32, f32, f32)> {
+    let wrap = |angle: f32| angle.rem_euclid(2f32 * PI);
+    let (sa, sb, ca, cb) = (a.sin(), b.sin(), a.cos(), b.cos());
+    let c_ab = (a - b).cos();
+    // rounding can push the square of a straight part of zero length slightly below zero
+    let root = |p_squared: f32| {
+        if p_squared < -1e-4 {
+            None
+        } else {
+            Some(p_squared.max(0f32).sqrt())
+        }
+    };
+    let middle_arc = |cos_p: f32| {
+        if cos_p.abs() > 1f32 {
+            None
+        } else {
+            Some(wrap(2f32 * PI - cos_p.acos()))
+        }
+    };
+    match *turns {
+        [Left, Straight, Left] => {
+            let p = root(2f32 + d * d - 2f32 * c_ab + 2f32 * d * (sa - sb))?;
+            let phi = (cb - ca).atan2(d + sa - sb);
+            Some((wrap(phi - a), p, wrap(b - phi)))
+        }
+        [Right, Straight, Right] => {
+            let p = root(2f32 + d * d - 2f32 * c_ab + 2f32 * d * (sb - sa))?;
+            let phi = (ca - cb).atan2(d - sa + sb);
+            Some((wrap(a - phi), p, wrap(phi - b)))
+        }
+        [Left, Straight, Right] => {
+            let p = root(-2f32 + d * d + 2f32 * c_ab + 2f32 * d * (sa + sb))?;
+            let phi = (-ca - cb).atan2(d + sa + sb) - (-2f32).atan2(p);
+            Some((wrap(phi - a), p, wrap(phi - b)))
+        }
+        [Right, Straight, Left] => {
+            let p = root(-2f32 + d * d + 2f32 * c_ab - 2f32 * d * (sa + sb))?;
+            let phi = (ca + cb).atan2(d - sa - sb) - 2f32.atan2(p);
+            Some((wrap(a - phi), p, wrap(b - phi)))
+        }
+        [Right, Left, Right] => {
+            let p = middle_arc((6f32 - d * d + 2f32 * c_ab + 2f32 * d * (sa - sb)) / 8f32)?;
+            let t = wrap(a - (ca - cb).atan2(d - sa + sb) + p / 2f32);
+            Some((t, p, wrap(a - b - t + p)))
+        }
+        [Left, Right, Left] => {
+            let p = middle_arc((6f32 - d * d + 2f32 * c_ab + 2f32 * d * (sb - sa)) / 8f32)?;
+            let t = wrap(-a - (ca - cb).atan2(d + sa - sb) + p / 2f32);
+            Some((t, p, wrap(b - a - t + p)))
+        }
+        _ => None,
+    }
+}
+
+// Location and heading after flying length along one part of a path from point at heading
+fn advance(point: &Point, heading: f32, turn: Turn, length: f32, radius: f32) -> (Point, f32) {
+    let (x, y, z) = (point.x, point.y, point.z);
+    match turn {
+        Straight => (
+            Point::new(x + length * heading.cos(), y + length * heading.sin(), z),
+            heading,
+        ),
+        Left => {
+            let next = heading + length / radius;
+            let point = Point::new(
+                x + radius * (next.sin() - heading.sin()),
+                y - radius * (next.cos() - heading.cos()),
+                z,
+            );
+            (point, next)
+        }
+        Right => {
+            let next = heading - length / radius;
+            let point = Point::new(
+                x - radius * (next.sin() - heading.sin()),
+                y + radius * (next.cos() - heading.cos()),
+                z,
+            );
+            (point, next)
+        }
+    }
+}
+
+// Headings the plane arrives at target in by turning either way from start at heading, then
+// flying straight; a turn can only reach targets outside its circle
+fn turn_headings(start: &Point, heading: f32, radius: f32, target: &Point) -> Vec<f32> {
+    let mut headings = Vec::new();
+    for &side in &[1f32, -1f32] {
+        let center = Point::new(
+            start.x - side * radius * heading.sin(),
+            start.y + side * radius * heading.cos(),
+            start.z,
+        );
+        // targets on the circle within rounding are reached at the end of the turn
+        let dist = center.distance(target);
+        if dist < radius * 0.999 {
+            continue;
+        }
+        let exit_angle = direction(&center, target) - side * (radius / dist).min(1f32).acos();
+        headings.push(exit_angle + side * PI / 2f32);
+    }
+    headings
+}
+
+// Direction from a to b in radians counter-clockwise from east
+fn direction(a: &Point, b: &Point) -> f32 {
+    (b.y - a.y).atan2(b.x - a.x)
+}
+
+impl Pathfinder {
+    // Replace the straight segments of legs by Dubins paths the plane can fly within its
+    // turning radius, leaving the plane at heading if known
+    // The plane passes each point of the route in the direction it arrives in, the one it leaves
+    // in, halfway between them, or the direction it gets there in after a single turn,
+    // whichever is shortest to fly; a segment with every Dubins path blocked is an error for the
+    // leg to the waypoint it belongs to
+    pub fn fly_dubins(
+        &self,
+        start: Location,
+        heading: Option<f32>,
+        legs: Vec<Option<LinkedList<Location>>>,
+        waypoints: &[Waypoint],
+    ) -> Result<Vec<Option<LinkedList<Location>>>, PathfinderError> {
+        // the whole route with the leg each point belongs to
+        let mut route = vec![Point::from_location(&start, &self.origin)];
+        let mut owners = vec![0];
+        for (i, leg) in legs.iter().enumerate() {
+            for location in leg.iter().flatten() {
+                route.push(Point::from_location(location, &self.origin));
+                owners.push(i);
+            }
+        }
+        if route.len() < 2 {
+            return Ok(legs);
+        }
+        let mut smoothed: Vec<Option<LinkedList<Location>>> = legs
+            .iter()
+            .map(|leg| leg.as_ref().map(|_| LinkedList::new()))
+            .collect();
+        let mut heading = heading.unwrap_or_else(|| direction(&route[0], &route[1]));
+        let mut time = 0f32;
+        for k in 1..route.len() {
+            let (a, b) = (route[k - 1], route[k]);
+            let arrive = direction(&a, &b);
+            let mut headings = turn_headings(&a, heading, self.turning_radius, &b);
+            headings.push(arrive);
+            if let Some(c) = route.get(k + 1) {
+                let leave = direction(&b, c);
+                let halfway = (arrive.sin() + leave.sin()).atan2(arrive.cos() + leave.cos());
+                headings.extend(&[halfway, leave]);
+            }
+            let mut candidates: Vec<Dubins> = headings
+                .iter()
+                .flat_map(|&end_heading| {
+                    Dubins::candidates(&a, heading, &b, end_heading, self.turning_radius)
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.length().partial_cmp(&b.length()).unwrap());
+            let path = if a.distance(&b) < 0.01 {
+                None
+            } else {
+                let path = candidates
+                    .into_iter()
+                    .find(|path| self.dubins_clear(path, time));
+                if path.is_none() {
+                    let leg = owners[k];
+                    return Err(PathfinderError::NoDubinsPath {
+                        from: leg.checked_sub(1).map(|i| waypoints[i].index),
+                        to: waypoints[leg].index,
+                    });
+                }
+                path
+            };
+            let points = match path {
+                Some(path) => {
+                    heading = path.at(path.length()).1;
+                    time += path.length() / self.speed;
+                    path.sample(PI / 8f32)
+                }
+                None => {
+                    heading = arrive;
+                    time += a.distance(&b) / self.speed;
+                    vec![b]
+                }
+            };
+            if let Some(ref mut leg) = smoothed[owners[k]] {
+                for point in points {
+                    leg.push_back(point.to_location(&self.origin));
+                }
+            }
+        }
+        Ok(smoothed)
+    }
+
+    // Check a Dubins path flown from time depart against obstacles and flyzones
+    // Obstacles are flown over at the altitude the path has there
+    fn dubins_clear(&self, path: &Dubins, depart: f32) -> bool {
+        let moving = self.obstacles.iter().any(|o| o.is_moving());
+        let mut a = path.start;
+        let mut time = depart;
+        for b in path.sample(PI / 16f32) {
+            match self.valid_path(&a, &b) {
+                PathValidity::Invalid => return false,
+                PathValidity::Flyover(_) if !self.flies_over(&a, &b) => return false,
+                _ => {}
+            }
+            if moving {
+                if let PathValidity::Invalid = self.valid_path_at(&a, &b, time) {
+                    return false;
+                }
+            }
+            time += a.distance(&b) / self.speed;
+            a = b;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn candidates_test() {
+        let start = Point::new(0f32, 0f32, 10f32);
+        let ends = [
+            (Point::new(30f32, 0f32, 20f32), 0f32),
+            (Point::new(0f32, 30f32, 20f32), PI),
+            (Point::new(5f32, 3f32, 20f32), -PI / 2f32),
+            (Point::new(-20f32, -7f32, 20f32), 2f32),
+        ];
+        for &(end, end_heading) in &ends {
+            let paths = Dubins::candidates(&start, 0.5, &end, end_heading, 5f32);
+            assert!(!paths.is_empty());
+            for path in &paths {
+                // every path reaches end in the right direction
+                let (point, heading) = path.at(path.length());
+                assert!(point.distance(&end) < 0.01);
+                assert!((point.z - end.z).abs() < 0.01);
+                let error = (heading - end_heading + PI).rem_euclid(2f32 * PI) - PI;
+                assert!(error.abs() < 0.001);
+            }
+        }
+        // straight ahead is a line
+        let paths = Dubins::candidates(&start, 0f32, &ends[0].0, 0f32, 5f32);
+        assert!((paths[0].length() - 30f32).abs() < 0.01);
+        // turning back around is at least half a circle
+        let paths = Dubins::candidates(&start, 0f32, &Point::new(0f32, 10f32, 10f32), PI, 5f32);
+        assert_eq!(paths[0].turns(), [Left, Straight, Left]);
+        assert!((paths[0].length() - 5f32 * PI).abs() < 0.01);
+    }
+
+    #[test]
+    fn dubins_test() {
+        let mut pathfinder = PathfinderBuilder::new()
+            .turning_radius(20f32)
+            .dubins(true)
+            .create(square_flyzone(), vec![center_obstacle(30f32, 200f32)])
+            .unwrap();
+        assert!(pathfinder.is_dubins());
+        // flying east, waypoints north then past the obstacle
+        let plane = Plane::new(at(100f32, 100f32, 50f32)).yaw(90f32);
+        let mut waypoints = LinkedList::new();
+        for (i, &(x, y)) in [(100f32, 300f32), (300f32, 200f32)].iter().enumerate() {
+            waypoints.push_back(Waypoint::new(i as u32, at(x, y, 50f32), 1f32));
+        }
+        let mut points = vec![project(&pathfinder, &plane.location)];
+        let origin = pathfinder.origin;
+        for wp in pathfinder.try_get_adjust_path(plane, waypoints).unwrap() {
+            points.push(Point::from_location(&wp.location, &origin));
+        }
+        assert!(points.len() > 10);
+        let center = project(&pathfinder, &at(200f32, 200f32, 200f32));
+        // leaves heading east
+        assert!(direction(&points[0], &points[1]).abs() < PI / 8f32);
+        for i in 1..points.len() {
+            let (a, b) = (&points[i - 1], &points[i]);
+            let (_, _, dist, _) = intersect_distance(a, b, &center);
+            assert!(dist.sqrt() > 29.9);
+            if i > 1 {
+                // turning no tighter than the turning radius
+                let turn = (direction(a, b) - direction(&points[i - 2], a) + PI)
+                    .rem_euclid(2f32 * PI)
+                    - PI;
+                let chord = points[i - 2].distance(a).max(a.distance(b)).min(40f32);
+                assert!(turn.abs() <= 2f32 * (chord / 40f32).asin() + 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn blocked_test() {
+        let pathfinder = PathfinderBuilder::new()
+            .turning_radius(40f32)
+            .create(square_flyzone(), vec![center_obstacle(30f32, 40f32)])
+            .unwrap();
+
+        // climbing across the obstacle, it is cleared only if the plane is high enough already
+        let high = Point::new(250f32, 200f32, 100f32);
+        assert!(!pathfinder.flies_over(&Point::new(150f32, 200f32, 20f32), &high));
+        assert!(pathfinder.flies_over(&Point::new(150f32, 200f32, 60f32), &high));
+
+        // heading south near a corner, the plane cannot turn back north inside the flyzone
+        let end = at(30f32, 60f32, 50f32);
+        let legs = vec![Some(vec![end].into_iter().collect())];
+        let waypoints = vec![Waypoint::new(3, end, 1f32)];
+        let start = at(30f32, 30f32, 50f32);
+        assert_eq!(
+            pathfinder
+                .fly_dubins(start, Some(-PI / 2f32), legs, &waypoints)
+                .err(),
+            Some(PathfinderError::NoDubinsPath { from: None, to: 3 })
+        );
+    }
+
+    #[test]
+    fn sample_test() {
+        let start = Point::new(0f32, 0f32, 10f32);
+        let end = Point::new(0f32, 10f32, 10f32);
+        let path = Dubins::candidates(&start, 0f32, &end, PI, 5f32)[0];
+        let points = path.sample(PI / 8f32);
+        assert_eq!(points.len(), 8);
+        assert_eq!(points[7].distance(&end), 0f32);
+        // points stay on the turning circle centered at (0, 5)
+        let center = Point::new(0f32, 5f32, 10f32);
+        for point in points {
+            assert!((point.distance(&center) - 5f32).abs() < 0.01);
+        }
+    }
+}
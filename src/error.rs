@@ -0,0 +1,130 @@
+// Errors reported by the pathfinder instead of panicking
+
+use std::error::Error;
+use std::fmt;
+
+use {ObstacleId, WaypointProblem};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathfinderError {
+    // At least one flyzone is required
+    NoFlyzone,
+    // Flyzone has less than 3 points
+    TooFewPoints {
+        flyzone: usize,
+        points: usize,
+    },
+    // Flyzone crosses itself; edges are given by the index of their first point
+    SelfIntersectingFlyzone {
+        flyzone: usize,
+        edges: (usize, usize),
+    },
+    // Hole of a flyzone has less than 3 points or crosses itself
+    InvalidHole {
+        flyzone: usize,
+        hole: usize,
+    },
+    // Exclusion polygon has less than 3 points or crosses itself
+    InvalidExclusion(usize),
+    // Pathfinder was used before init
+    Uninitialized,
+    // No path between two waypoints; a missing start index means the plane position
+    UnreachableLeg {
+        from: Option<u32>,
+        to: u32,
+    },
+    // Every Dubins path of a segment on the leg to waypoint to is blocked; a missing start
+    // index means the plane position
+    NoDubinsPath {
+        from: Option<u32>,
+        to: u32,
+    },
+    // Planning exceeded max_process_time
+    Timeout,
+    // Config file could not be read or parsed
+    InvalidConfig(String),
+    // Vehicle limit or buffer out of range
+    InvalidParameter {
+        name: &'static str,
+        value: f32,
+    },
+    // Obstacle id was never given out or was already removed
+    UnknownObstacle(ObstacleId),
+    // Waypoint is below the floor or above the ceiling of the flyzones around it
+    WaypointAltitude {
+        waypoint: u32,
+        alt: f32,
+    },
+    // Waypoint cannot be reached, and could not be moved if waypoints are repaired
+    InvalidWaypoint {
+        waypoint: u32,
+        problem: WaypointProblem,
+    },
+}
+
+impl fmt::Display for PathfinderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathfinderError::NoFlyzone => write!(f, "require at least one flyzone"),
+            PathfinderError::TooFewPoints { flyzone, points } => write!(
+                f,
+                "flyzone {} has {} points, require at least 3 points",
+                flyzone, points
+            ),
+            PathfinderError::SelfIntersectingFlyzone { flyzone, edges } => write!(
+                f,
+                "flyzone {} intersects itself between edges {} and {}",
+                flyzone, edges.0, edges.1
+            ),
+            PathfinderError::InvalidHole { flyzone, hole } => write!(
+                f,
+                "hole {} of flyzone {} has less than 3 points or intersects itself",
+                hole, flyzone
+            ),
+            PathfinderError::InvalidExclusion(exclusion) => write!(
+                f,
+                "exclusion {} has less than 3 points or intersects itself",
+                exclusion
+            ),
+            PathfinderError::Uninitialized => write!(f, "pathfinder is not initialized"),
+            PathfinderError::UnreachableLeg {
+                from: Some(from),
+                to,
+            } => {
+                write!(f, "no path from waypoint {} to waypoint {}", from, to)
+            }
+            PathfinderError::UnreachableLeg { from: None, to } => {
+                write!(f, "no path from plane to waypoint {}", to)
+            }
+            PathfinderError::NoDubinsPath {
+                from: Some(from),
+                to,
+            } => write!(
+                f,
+                "no turn within the turning radius from waypoint {} to waypoint {}",
+                from, to
+            ),
+            PathfinderError::NoDubinsPath { from: None, to } => write!(
+                f,
+                "no turn within the turning radius from plane to waypoint {}",
+                to
+            ),
+            PathfinderError::Timeout => write!(f, "exceeded maximum process time"),
+            PathfinderError::InvalidConfig(ref e) => write!(f, "invalid config: {}", e),
+            PathfinderError::InvalidParameter { name, value } => {
+                write!(f, "invalid value {} for {}", value, name)
+            }
+            PathfinderError::UnknownObstacle(id) => write!(f, "unknown obstacle {:?}", id),
+            PathfinderError::WaypointAltitude { waypoint, alt } => write!(
+                f,
+                "waypoint {} at {} m is outside the flyzone altitudes",
+                waypoint, alt
+            ),
+            PathfinderError::InvalidWaypoint { waypoint, problem } => {
+                write!(f, "waypoint {} is {}", waypoint, problem)
+            }
+        }
+    }
+}
+
+impl Error for PathfinderError {}
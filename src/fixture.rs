@@ -0,0 +1,56 @@
+// Setup shared by the unit tests: a 400 m square flyzone, crossed from the plane on its west
+// side to a waypoint on its east side
+// Test locations are given in meters from origin, the pathfinder plans them in its own projection
+
+use super::*;
+
+pub fn origin() -> Location {
+    Location::from_degrees(0f64, 0f64, 0f32)
+}
+
+// Location x meters east and y meters north of origin, z meters high
+pub fn at(x: f32, y: f32, z: f32) -> Location {
+    Point::new(x, y, z).to_location(&origin())
+}
+
+// Location as a point in the projection pathfinder plans in
+pub fn project(pathfinder: &Pathfinder, location: &Location) -> Point {
+    Point::from_location(location, &pathfinder.origin)
+}
+
+pub fn square_flyzone() -> Vec<Vec<Location>> {
+    vec![vec![
+        at(0f32, 0f32, 10f32),
+        at(0f32, 400f32, 10f32),
+        at(400f32, 400f32, 10f32),
+        at(400f32, 0f32, 10f32),
+    ]]
+}
+
+// Cylinder standing in the middle of the square flyzone
+pub fn center_obstacle(radius: f32, height: f32) -> Obstacle {
+    Obstacle::new(at(200f32, 200f32, height), radius, height)
+}
+
+// Plane at (50, 200) and waypoint 0 at (350, 200), flying at the altitudes given
+pub fn crossing(plane_alt: f32, waypoint_alt: f32) -> (Plane, LinkedList<Waypoint>) {
+    let plane = Plane::new(at(50f32, 200f32, plane_alt));
+    let mut waypoints = LinkedList::new();
+    waypoints.push_back(Waypoint::new(0, at(350f32, 200f32, waypoint_alt), 1f32));
+    (plane, waypoints)
+}
+
+// Points of the adjusted path across the square flyzone at altitude, starting with the plane
+pub fn cross_square(pathfinder: &mut Pathfinder, altitude: f32) -> Vec<Point> {
+    let (plane, waypoints) = crossing(altitude, altitude);
+    let mut points = vec![project(pathfinder, &plane.location)];
+    let origin = pathfinder.origin;
+    points.extend(
+        pathfinder
+            .try_get_adjust_path(plane, waypoints)
+            .unwrap()
+            .iter()
+            .map(|wp| Point::from_location(&wp.location, &origin)),
+    );
+    points
+}
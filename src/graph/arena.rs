@@ -0,0 +1,374 @@
+use super::*;
+
+// Storage for nodes and vertices that tangents can be added to
+pub trait Arena {
+    fn node(&self, id: NodeId) -> &Node;
+
+    fn vertex(&self, id: VertexId) -> &Vertex;
+
+    fn num_vertices(&self) -> usize;
+
+    fn add_node(&mut self, node: Node) -> NodeId;
+
+    // Insert vertex into the ring given by the sign of its angle
+    fn insert_vertex(&mut self, vertex: Vertex) -> VertexId;
+
+    // Next vertex along the ring, wrapping around after the last one
+    fn next_vertex(&self, id: VertexId) -> Option<VertexId>;
+
+    // Direction from the center of node a to the center of node b
+    fn bearing(&self, a: NodeId, b: NodeId) -> f32 {
+        let (c1, c2) = (self.node(a).origin, self.node(b).origin);
+        (c2.y - c1.y).atan2(c2.x - c1.x)
+    }
+
+    // Add a vertex at angle on node, traveled counter-clockwise in the left ring or clockwise
+    // in the right ring
+    fn add_vertex(
+        &mut self,
+        node: NodeId,
+        angle: f32,
+        left: bool,
+        connection: Option<Connection>,
+    ) -> VertexId {
+        let vertex = Vertex::new(self.node(node), node, ring_angle(angle, left), connection);
+        self.insert_vertex(vertex)
+    }
+
+    // Mark an angle on node that cannot be passed while hugging it, in both rings
+    // Overlap is the node that blocks the way, if any
+    fn add_sentinel(&mut self, node: NodeId, angle: f32, overlap: Option<NodeId>) {
+        for left in [true, false] {
+            let mut vertex = Vertex::new_sentinel(self.node(node), node, ring_angle(angle, left));
+            vertex.overlap = overlap;
+            self.insert_vertex(vertex);
+        }
+    }
+
+    // Add a tangent leaving node from at from_angle and arriving on node to at to_angle
+    // Vertices go in the ring matching the direction the plane turns around each node
+    // Returns the departure vertex
+    fn add_edge(
+        &mut self,
+        from: NodeId,
+        from_angle: f32,
+        to: NodeId,
+        to_angle: f32,
+        distance: f32,
+        threshold: f32,
+    ) -> VertexId {
+        let p1 = self.node(from).to_point(from_angle);
+        let p2 = self.node(to).to_point(to_angle);
+        let direction = (p2.x - p1.x, p2.y - p1.y);
+        let depart_left = turns_left(&self.node(from).origin, &p1, direction);
+        let arrive_left = turns_left(&self.node(to).origin, &p2, direction);
+        let v = self.add_vertex(to, to_angle, arrive_left, None);
+        let edge = Connection::new(v, distance, threshold);
+        self.add_vertex(from, from_angle, depart_left, Some(edge))
+    }
+
+    // Next vertex along the ring and the length of the arc to reach it
+    fn next(&self, id: VertexId) -> Option<(VertexId, f32)> {
+        let next = self.next_vertex(id)?;
+        let (vertex, next_vertex) = (self.vertex(id), self.vertex(next));
+        let sweep = if vertex.angle > 0f32 {
+            next_vertex.angle - vertex.angle
+        } else {
+            vertex.angle - next_vertex.angle
+        };
+        Some((next, sweep.rem_euclid(2f32 * PI) * vertex.radius))
+    }
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Graph::default()
+    }
+
+    // Remove every edge touching a node and the sentinels it placed on other nodes; the node
+    // is marked as removed and its slot, along with those of its vertices, is reused later
+    pub fn remove_node(&mut self, id: NodeId) {
+        for i in 0..self.vertices.len() {
+            let vertex = &self.vertices[i];
+            let touching = match vertex.connection {
+                Some(ref connection) => {
+                    vertex.node == id || self.vertex(connection.neighbor).node == id
+                }
+                None => false,
+            };
+            if touching || vertex.overlap == Some(id) {
+                self.remove_edge(VertexId(i));
+            }
+        }
+        let node = &mut self.nodes[id.0];
+        node.removed = true;
+        self.free_vertices.append(&mut node.left_ring);
+        self.free_vertices.append(&mut node.right_ring);
+        self.free_nodes.push(id);
+    }
+
+    // Remove the tangents between nodes a and b, in both directions
+    pub fn remove_edges(&mut self, a: NodeId, b: NodeId) {
+        for i in 0..self.vertices.len() {
+            let vertex = &self.vertices[i];
+            let between = match vertex.connection {
+                Some(ref connection) => {
+                    let neighbor = self.vertex(connection.neighbor).node;
+                    (vertex.node, neighbor) == (a, b) || (vertex.node, neighbor) == (b, a)
+                }
+                None => false,
+            };
+            if between {
+                self.remove_edge(VertexId(i));
+            }
+        }
+    }
+
+    // Take a vertex out of its ring, along with the vertex its connection arrives at
+    pub fn remove_edge(&mut self, id: VertexId) {
+        if let Some(connection) = self.vertices[id.0].connection.take() {
+            self.detach(connection.neighbor);
+        }
+        self.detach(id);
+    }
+
+    // Vertices already out of their ring are left alone, their slot is free
+    fn detach(&mut self, id: VertexId) {
+        if !self.ring(self.vertex(id)).contains(&id) {
+            return;
+        }
+        if let Some(prev) = self.prev_vertex(id) {
+            self.changes.push(prev);
+        }
+        self.changes.push(id);
+        let vertex = &self.vertices[id.0];
+        let node = &mut self.nodes[vertex.node.0];
+        let ring = if vertex.angle > 0f32 {
+            &mut node.left_ring
+        } else {
+            &mut node.right_ring
+        };
+        ring.retain(|v| *v != id);
+        self.free_vertices.push(id);
+    }
+
+    // Vertex leading to id along its ring, none if id is alone
+    pub fn prev_vertex(&self, id: VertexId) -> Option<VertexId> {
+        let ring = self.ring(self.vertex(id));
+        let position = ring.partition_point(|v| ring_key(self, *v) < ring_key(self, id));
+        if ring.is_empty() {
+            return None;
+        }
+        let prev = ring[(position + ring.len() - 1) % ring.len()];
+        if prev == id {
+            None
+        } else {
+            Some(prev)
+        }
+    }
+}
+
+impl Arena for Graph {
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    fn vertex(&self, id: VertexId) -> &Vertex {
+        &self.vertices[id.0]
+    }
+
+    fn num_vertices(&self) -> usize {
+        self.vertices.len()
+    }
+
+    fn add_node(&mut self, node: Node) -> NodeId {
+        if let Some(id) = self.free_nodes.pop() {
+            self.nodes[id.0] = node;
+            return id;
+        }
+        self.nodes.push(node);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    fn insert_vertex(&mut self, vertex: Vertex) -> VertexId {
+        let id = self
+            .free_vertices
+            .pop()
+            .unwrap_or(VertexId(self.vertices.len()));
+        let key = (vertex.angle.abs(), id.0);
+        let position = ring_position(self.ring(&vertex), key, |v| ring_key(self, v));
+        let node = &mut self.nodes[vertex.node.0];
+        let ring = if vertex.angle > 0f32 {
+            &mut node.left_ring
+        } else {
+            &mut node.right_ring
+        };
+        ring.insert(position, id);
+        // the vertex before now leads to the new one
+        if ring.len() > 1 {
+            let prev = ring[(position + ring.len() - 1) % ring.len()];
+            self.changes.push(prev);
+        }
+        self.changes.push(id);
+        if id.0 < self.vertices.len() {
+            self.vertices[id.0] = vertex;
+        } else {
+            self.vertices.push(vertex);
+        }
+        id
+    }
+
+    fn next_vertex(&self, id: VertexId) -> Option<VertexId> {
+        ring_successor(&[self.ring(self.vertex(id))], id, |v| ring_key(self, v))
+    }
+}
+
+impl Graph {
+    fn ring(&self, vertex: &Vertex) -> &[VertexId] {
+        let node = &self.nodes[vertex.node.0];
+        if vertex.angle > 0f32 {
+            &node.left_ring
+        } else {
+            &node.right_ring
+        }
+    }
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(graph: &'a Graph) -> Self {
+        Overlay {
+            graph: graph,
+            nodes: Vec::new(),
+            vertices: Vec::new(),
+            rings: HashMap::new(),
+        }
+    }
+
+    // Vertices added to the ring of vertex by this overlay
+    fn ring(&self, vertex: &Vertex) -> &[VertexId] {
+        let left = vertex.angle > 0f32;
+        match vertex.node.0.checked_sub(self.graph.nodes.len()) {
+            Some(i) if left => &self.nodes[i].left_ring,
+            Some(i) => &self.nodes[i].right_ring,
+            None => match self.rings.get(&(vertex.node, left)) {
+                Some(ring) => ring,
+                None => &[],
+            },
+        }
+    }
+}
+
+impl<'a> Arena for Overlay<'a> {
+    fn node(&self, id: NodeId) -> &Node {
+        match id.0.checked_sub(self.graph.nodes.len()) {
+            Some(i) => &self.nodes[i],
+            None => self.graph.node(id),
+        }
+    }
+
+    fn vertex(&self, id: VertexId) -> &Vertex {
+        match id.0.checked_sub(self.graph.vertices.len()) {
+            Some(i) => &self.vertices[i],
+            None => self.graph.vertex(id),
+        }
+    }
+
+    fn num_vertices(&self) -> usize {
+        self.graph.vertices.len() + self.vertices.len()
+    }
+
+    fn add_node(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.graph.nodes.len() + self.nodes.len() - 1)
+    }
+
+    fn insert_vertex(&mut self, vertex: Vertex) -> VertexId {
+        let id = VertexId(self.num_vertices());
+        let key = (vertex.angle.abs(), id.0);
+        let position = ring_position(self.ring(&vertex), key, |v| ring_key(self, v));
+        let left = vertex.angle > 0f32;
+        let ring = match vertex.node.0.checked_sub(self.graph.nodes.len()) {
+            Some(i) if left => &mut self.nodes[i].left_ring,
+            Some(i) => &mut self.nodes[i].right_ring,
+            None => self.rings.entry((vertex.node, left)).or_default(),
+        };
+        ring.insert(position, id);
+        self.vertices.push(vertex);
+        id
+    }
+
+    fn next_vertex(&self, id: VertexId) -> Option<VertexId> {
+        let vertex = self.vertex(id);
+        if vertex.node.0 < self.graph.nodes.len() {
+            let rings = [self.graph.ring(vertex), self.ring(vertex)];
+            ring_successor(&rings, id, |v| ring_key(self, v))
+        } else {
+            ring_successor(&[self.ring(vertex)], id, |v| ring_key(self, v))
+        }
+    }
+}
+
+// Vertices in a ring are ordered by the distance traveled around the node from angle 0,
+// vertices at the same angle in the order they were added
+fn ring_key<A: Arena + ?Sized>(arena: &A, id: VertexId) -> (f32, usize) {
+    (arena.vertex(id).angle.abs(), id.0)
+}
+
+// Index to insert a vertex with key into a sorted ring
+fn ring_position<F: Fn(VertexId) -> (f32, usize)>(
+    ring: &[VertexId],
+    key: (f32, usize),
+    key_of: F,
+) -> usize {
+    ring.partition_point(|v| key_of(*v) <= key)
+}
+
+// Vertex following id in the union of sorted rings, none if id is alone
+fn ring_successor<F: Fn(VertexId) -> (f32, usize)>(
+    rings: &[&[VertexId]],
+    id: VertexId,
+    key_of: F,
+) -> Option<VertexId> {
+    let key = key_of(id);
+    let smallest = |candidates: Vec<VertexId>| {
+        candidates
+            .into_iter()
+            .min_by(|a, b| key_of(*a).partial_cmp(&key_of(*b)).unwrap())
+    };
+    let after = rings
+        .iter()
+        .filter_map(|ring| {
+            let position = ring_position(ring, key, &key_of);
+            ring.get(position).cloned()
+        })
+        .collect();
+    let next = match smallest(after) {
+        Some(next) => next,
+        None => smallest(
+            rings
+                .iter()
+                .filter_map(|ring| ring.first().cloned())
+                .collect(),
+        )?,
+    };
+    if next == id {
+        None
+    } else {
+        Some(next)
+    }
+}
+
+// Angle encoded for a ring, (0, 2PI] in the left ring and [-2PI, 0) in the right ring
+fn ring_angle(angle: f32, left: bool) -> f32 {
+    let angle = angle.rem_euclid(2f32 * PI);
+    match (left, angle == 0f32) {
+        (true, true) => 2f32 * PI,
+        (true, false) => angle,
+        (false, _) => angle - 2f32 * PI,
+    }
+}
+
+// true if moving from p along direction circles center counter-clockwise
+fn turns_left(center: &Point, p: &Point, direction: (f32, f32)) -> bool {
+    (p.x - center.x) * direction.1 - (p.y - center.y) * direction.0 >= 0f32
+}
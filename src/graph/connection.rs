@@ -1,7 +1,7 @@
 use super::*;
 
 impl Connection {
-    pub fn new(neighbor: Rc<RefCell<Vertex>>, distance: f32, threshold: f32) -> Self {
+    pub fn new(neighbor: VertexId, distance: f32, threshold: f32) -> Self {
         Connection {
             neighbor: neighbor,
             distance: distance,
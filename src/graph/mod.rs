@@ -4,14 +4,18 @@ use super::*;
 #[cfg(test)]
 mod test;
 
+mod arena;
 mod connection;
+mod moving;
 mod node;
 mod point;
 pub mod util;
 mod vertex;
 
+pub use graph::arena::Arena;
 pub use graph::util::*;
-use obj::{Location, Obstacle};
+use obj::{Location, Obstacle, Shape};
+use std::collections::HashMap;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Point {
@@ -20,26 +24,30 @@ pub struct Point {
     pub z: f32,
 }
 
+// Index of a node in the graph arena
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct NodeId(pub usize);
+
+// Index of a vertex in the graph arena
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct VertexId(pub usize);
+
 #[derive(Debug)]
 pub struct Vertex {
-    pub index: i32,                          // Index to identify vertex
-    pub radius: f32,                         // Radius of the node vertex is attached to
-    pub location: Point,                     // Location of the vertex
-    pub angle: f32,                          // Angle with respect to the node
-    pub g_cost: f32,                         //
-    pub f_cost: f32,                         //
-    pub parent: Option<Rc<RefCell<Vertex>>>, // Paren of vertex 
-    pub connection: Option<Connection>,      // Edge connecting to another node
-    pub prev: Option<Rc<RefCell<Vertex>>>,   // Previous neighbor vertex in the same node
-    pub next: Option<Rc<RefCell<Vertex>>>,   // Neighbor vertex in the same node
-    pub sentinel: bool,                      // Sentinel property marks end of path hugging
+    pub node: NodeId,                   // Node the vertex is attached to
+    pub radius: f32,                    // Radius of the node vertex is attached to
+    pub location: Point,                // Location of the vertex
+    pub angle: f32,                     // Angle with respect to the node, positive in left ring
+    pub connection: Option<Connection>, // Edge connecting to another node
+    pub sentinel: bool,                 // Sentinel property marks end of path hugging
+    pub overlap: Option<NodeId>,        // Node whose overlap placed the sentinel
 }
 
 // Represent a connection between two nodes
 // Contains the coordinate of tangent line and distance
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub struct Connection {
-    pub neighbor: Rc<RefCell<Vertex>>, // Connected node through a tangent
+    pub neighbor: VertexId, // Connected node through a tangent
     pub distance: f32,
     // starting and ending vertices must be above threshold to take the connection
     pub threshold: f32,
@@ -64,165 +72,432 @@ impl From<PathValidity> for bool {
 pub struct Node {
     pub origin: Point,
     pub radius: f32,
-    pub height: f32,                        // make private later
-    pub left_ring: Rc<RefCell<Vertex>>,     // make private later
-    pub right_ring: Rc<RefCell<Vertex>>,    // make private later
+    pub height: f32,               // make private later
+    pub left_ring: Vec<VertexId>,  // Counter-clockwise, sorted by increasing angle
+    pub right_ring: Vec<VertexId>, // Clockwise, sorted by decreasing angle
+    pub removed: bool,             // Obstacle was removed, the index is kept for stability
+    pub goal: bool,                // Destination of a replanned leg, only entered by its search
+}
+
+// Nodes and vertices of the visibility graph, addressed by index so that the graph holds no
+// reference cycles and can be shared between threads
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub vertices: Vec<Vertex>,
+    pub changes: Vec<VertexId>, // Vertices whose way out changed since the graph was built
+    pub free_nodes: Vec<NodeId>, // Slots of removed nodes, reused by the next ones added
+    pub free_vertices: Vec<VertexId>, // Slots of vertices taken out of their rings
+}
+
+// Nodes and vertices added by a single search on top of the shared graph, which is left
+// untouched so that searches can run at the same time
+#[derive(Debug)]
+pub struct Overlay<'a> {
+    pub graph: &'a Graph,
+    pub nodes: Vec<Node>,
+    pub vertices: Vec<Vertex>,
+    pub rings: HashMap<(NodeId, bool), Vec<VertexId>>, // Added vertices in rings of the graph
 }
 
 impl Pathfinder {
     pub fn build_graph(&mut self) {
         self.populate_nodes();
-        for i in 0..self.nodes.len() {
-            for j in i + 1..self.nodes.len() {
-                let (paths, obs_sentinels) =
-                    self.find_path(&self.nodes[i].borrow(), &self.nodes[j].borrow());
-                for (alpha, beta, distance, threshold) in paths {
-                    // println!("path: {} {} {}", alpha, beta, distance);
-                    let v = Rc::new(RefCell::new(Vertex::new(
-                        self.nodes[i].clone(),
-                        &mut self.num_vertices,
-                        beta,
-                        None,
-                    )));
-                    let edge = Connection::new(v, distance, threshold);
-                    let u = Rc::new(RefCell::new(Vertex::new(
-                        self.nodes[j].clone(),
-                        &mut self.num_vertices,
-                        alpha,
-                        Some(edge),
-                    )));
-                    self.nodes[i].borrow_mut().insert_vertex(u);
-                    // reciprocal
-                    let v = Rc::new(RefCell::new(Vertex::new(
-                        self.nodes[i].clone(),
-                        &mut self.num_vertices,
-                        (2f32 * PI - alpha) % (2f32 * PI),
-                        None,
-                    )));
-                    let edge = Connection::new(v, distance, threshold);
-                    let u = Rc::new(RefCell::new(Vertex::new(
-                        self.nodes[j].clone(),
-                        &mut self.num_vertices,
-                        (2f32 * PI - beta) % (2f32 * PI),
-                        Some(edge),
-                    )));
-                    self.nodes[j].borrow_mut().insert_vertex(u);
+        for i in 0..self.graph.nodes.len() {
+            for j in i + 1..self.graph.nodes.len() {
+                self.connect_nodes(NodeId(i), NodeId(j), true);
+            }
+        }
+        // searches kept for replanning refer to the old graph
+        self.graph.changes.clear();
+        self.replanners.clear();
+        self.prepared = false;
+        let (nodes, vertices) = (self.graph.nodes.len(), self.graph.vertices.len());
+        self.observer.graph_built(nodes, vertices);
+    }
+
+    // Add tangents in both directions between nodes a and b, and sentinels where they overlap
+    fn connect_nodes(&mut self, a: NodeId, b: NodeId, sentinels: bool) {
+        let (paths, obs_sentinels) = self.find_path(self.graph.node(a), self.graph.node(b));
+        let theta0 = self.graph.bearing(a, b);
+        for (alpha, beta, distance, threshold) in paths {
+            self.graph
+                .add_edge(a, theta0 + alpha, b, theta0 + beta, distance, threshold);
+            // reciprocal
+            self.graph
+                .add_edge(b, theta0 + beta, a, theta0 + alpha, distance, threshold);
+        }
+        if let (true, Some(obs_sentinels)) = (sentinels, obs_sentinels) {
+            // the last two pairs are the same intersections given for the other ring
+            for (alpha_s, beta_s) in obs_sentinels.into_iter().take(2) {
+                self.graph.add_sentinel(a, theta0 + alpha_s, Some(b));
+                self.graph.add_sentinel(b, theta0 + beta_s, Some(a));
+            }
+        }
+    }
+
+    // Add the nodes of the last obstacle in the list, connecting them to the rest of the graph
+    // and dropping edges it blocks
+    pub fn insert_obstacle_node(&mut self) -> Vec<NodeId> {
+        let obstacle = self.obstacles[self.obstacles.len() - 1].clone();
+        self.revalidate_edges(&obstacle);
+        let mut nodes = Vec::new();
+        for node in self.obstacle_node_list(&obstacle) {
+            let node = self.graph.add_node(node);
+            for i in 0..self.graph.nodes.len() {
+                if i != node.0 && !self.graph.nodes[i].removed {
+                    self.connect_nodes(node, NodeId(i), true);
                 }
-                if obs_sentinels.is_some() {
-                    for (alpha_s, beta_s) in obs_sentinels.unwrap() {
-                        let mut a = Vertex::new_sentinel(
-                            &mut self.num_vertices,
-                            self.nodes[i].borrow().origin.clone(),
-                            alpha_s,
-                        );
-                        //a.sentinel = true;
-                        let mut b = Vertex::new_sentinel(
-                            &mut self.num_vertices,
-                            self.nodes[j].borrow().origin.clone(),
-                            beta_s,
-                        );
-                        //b.;
-                        let s_a = Rc::new(RefCell::new(a));
-                        let s_b = Rc::new(RefCell::new(b));
-                        self.nodes[i].borrow_mut().insert_vertex(s_a);
-                        self.nodes[j].borrow_mut().insert_vertex(s_b);
-                    }
+            }
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    // Add the destination of a replanned leg to the graph, with tangents from every obstacle
+    pub fn insert_goal_node(&mut self, end: Point) -> NodeId {
+        let mut node = Node::new(end, 0f32, end.z);
+        node.goal = true;
+        let goal = self.graph.add_node(node);
+        for i in 0..self.graph.nodes.len() {
+            let other = self.graph.node(NodeId(i));
+            if i != goal.0 && !other.removed && !other.goal {
+                // tangents are found toward the goal, as adjust_path does for its end
+                self.connect_nodes(NodeId(i), goal, false);
+            }
+        }
+        goal
+    }
+
+    // Remove the nodes of an obstacle already taken out of the list, and recompute the tangents
+    // it could have blocked
+    pub fn remove_obstacle_node(&mut self, nodes: &[NodeId], obstacle: &Obstacle) {
+        for &node in nodes {
+            self.graph.remove_node(node);
+        }
+        let center = Point::from_location(&obstacle.location, &self.origin);
+        let active: Vec<NodeId> = (0..self.graph.nodes.len())
+            .map(NodeId)
+            .filter(|&id| !self.graph.node(id).removed)
+            .collect();
+        for (k, &a) in active.iter().enumerate() {
+            for &b in &active[k + 1..] {
+                let (node_a, node_b) = (self.graph.node(a), self.graph.node(b));
+                // every tangent between a and b stays within the larger radius of the line
+                // between their centers
+                let (_, _, dist, _) = intersect_distance(&node_a.origin, &node_b.origin, &center);
+                let radius = obstacle.radius + self.obstacle_buffer(obstacle);
+                if dist.sqrt() < radius + node_a.radius.max(node_b.radius) {
+                    self.graph.remove_edges(a, b);
+                    self.connect_nodes(a, b, false);
                 }
             }
         }
+    }
 
-        // for i in 0..self.nodes.len() {
-        //     println!("Node {}: {}\n", i, self.nodes[i].borrow());
-        // }
+    // Check edges crossing a new obstacle again, dropping the ones that are no longer valid
+    fn revalidate_edges(&mut self, obstacle: &Obstacle) {
+        for i in 0..self.graph.vertices.len() {
+            let vertex = &self.graph.vertices[i];
+            let neighbor = match vertex.connection {
+                Some(ref connection) => self.graph.vertex(connection.neighbor),
+                None => continue,
+            };
+            let p1 = self.graph.node(vertex.node).to_point(vertex.angle);
+            let p2 = self.graph.node(neighbor.node).to_point(neighbor.angle);
+            if self.perpendicular_intersect(&p1, &p2, obstacle).0.is_none() {
+                continue;
+            }
+            match self.valid_path(&p1, &p2) {
+                PathValidity::Invalid => self.graph.remove_edge(VertexId(i)),
+                PathValidity::Flyover(h_min) => {
+                    if let Some(ref mut connection) = self.graph.vertices[i].connection {
+                        connection.threshold = h_min;
+                    }
+                }
+                PathValidity::Valid => {}
+            }
+        }
     }
 
     fn populate_nodes(&mut self) {
-        self.nodes.clear();
+        self.graph = Graph::new();
         self.find_origin();
+        self.project_areas();
+        self.obstacle_nodes.clear();
         for obs in &self.obstacles {
-            let mut node = Node::from_obstacle(obs, &self.origin);
-            self.nodes.push(Rc::new(RefCell::new(node)));
+            // moving obstacles get a node for each search instead
+            if obs.is_moving() {
+                self.obstacle_nodes.push(Vec::new());
+                continue;
+            }
+            let mut ids = Vec::new();
+            for node in self.obstacle_node_list(obs) {
+                ids.push(self.graph.add_node(node));
+            }
+            self.obstacle_nodes.push(ids);
+        }
+        for i in 0..self.flyzones.len() {
+            self.virtualize_flyzone(i);
         }
-         for i in 0..self.flyzones.len() {
-             self.virtualize_flyzone(i);
+        for polygon in self.exclusion_polygons().to_vec() {
+            self.virtualize_exclusion(&polygon);
         }
     }
 
+    // Add a node at each corner of a flyzone for the plane to turn in or to go around
+    // Outer corners get a node inside them, moved further in when the edges beside the corner are
+    // too short for it to fit, and inner corners get one outside of the flyzone that the plane
+    // goes around. Repeated points and nearly straight corners are skipped.
     pub fn virtualize_flyzone(&mut self, index: usize) {
+        let boundary = self.boundaries[index].clone();
+        let mut points = simplify_polygon(&boundary, MIN_EDGE, STRAIGHT_ANGLE);
+        // tangents between the nodes are flown at the floor of the flyzone
         let flyzone = &self.flyzones[index];
-        // convert flyzone to points
-        let mut flyzone_points = Vec::new();
-        for location in flyzone {
-            let point = Point::from_location(&location, &self.origin);
-            flyzone_points.push(point);
-        }
-        // determine flyzone directions
-        let size = flyzone.len() as isize - 1;
-        let (clockwise, _) = vertex_direction(&flyzone_points);
-        let (direction, mut iter): (isize, isize) = if clockwise == true {
-            (1, 0)
-        } else {
-            (-1, size)
-        };
-        // edge conditions for flyzone
-        while (iter <= size) && (iter > -1) {
-            let (prev, next) = if iter == 0 {
-                (size, iter + 1)
-            } else if iter == size {
-                (iter - 1, 0)
-            } else {
-                (iter - 1, iter + 1)
-            };
-            println!("prev iter next: {} {} {}", prev, iter, next);
-            // initalize obstacle location
-            let a = flyzone_points[prev as usize];
-            let vertex = flyzone_points[iter as usize];
-            let b = flyzone_points[next as usize];
-            println!("{:?} {:?} {:?}", a, vertex, b);
-            let vec_a = (a.x - vertex.x, a.y - vertex.y);
-            let vec_b = (b.x - vertex.x, b.y - vertex.y);
-            let mag_a = ((vec_a.0).powi(2) + (vec_a.1).powi(2)).sqrt();
-            let mag_b = ((vec_b.0).powi(2) + (vec_b.1).powi(2)).sqrt();
+        let height = 0f32.max(flyzone.min_alt).min(flyzone.max_alt);
+        if points.len() < 3 {
+            return;
+        }
+        // walk the corners clockwise, so outer corners turn right
+        let (clockwise, _) = vertex_direction(&points);
+        if !clockwise {
+            points.reverse();
+        }
+        let n = points.len();
+        for i in 0..n {
+            let (prev, vertex, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            let vec_a = (prev.x - vertex.x, prev.y - vertex.y);
+            let vec_b = (next.x - vertex.x, next.y - vertex.y);
+            let mag_a = (vec_a.0.powi(2) + vec_a.1.powi(2)).sqrt();
+            let mag_b = (vec_b.0.powi(2) + vec_b.1.powi(2)).sqrt();
             let bisect = (
-                mag_b * vec_a.0 + mag_a * vec_b.0,
-                mag_b * vec_a.1 + mag_a * vec_b.1,
+                vec_a.0 / mag_a + vec_b.0 / mag_b,
+                vec_a.1 / mag_a + vec_b.1 / mag_b,
             );
-            let mag_bisection = ((bisect.0).powi(2) + (bisect.1).powi(2)).powf(0.5);
+            let mag_bisection = (bisect.0.powi(2) + bisect.1.powi(2)).sqrt();
             let bisection = (bisect.0 / mag_bisection, bisect.1 / mag_bisection);
+            let along =
+                |d: f32| Point::new(vertex.x + d * bisection.0, vertex.y + d * bisection.1, 0f32);
+            let turn = turn_angle(&prev, &vertex, &next);
+            if turn > 0f32 {
+                // inner corner, the bisection points out of the flyzone
+                let node = Node::new(along(self.turning_radius), self.turning_radius, height);
+                self.graph.add_node(node);
+                continue;
+            }
+            // as deep in the corner as the node fits without crossing the flyzone's edges
+            let theta = PI + turn;
+            let d = self.turning_radius / (theta / 2f32).sin();
+            let center = (0..=CORNER_STEPS)
+                .map(|step| along(d * (1f32 + step as f32 / CORNER_STEPS as f32)))
+                .find(|center| {
+                    inside(center, &boundary)
+                        && distance_to_edges(center, &boundary) > self.turning_radius - 0.001
+                });
+            // sharp corners have no room for a node
+            if let Some(center) = center {
+                self.graph
+                    .add_node(Node::new(center, self.turning_radius, height));
+            }
+        }
+    }
+
+    // Add a node at each convex corner of an exclusion polygon for the plane to turn around
+    fn virtualize_exclusion(&mut self, polygon: &[Point]) {
+        for node in self.corner_nodes(polygon, self.buffer, 0f32) {
+            self.graph.add_node(node);
+        }
+    }
+
+    // Nodes at each convex corner of polygon, placed outside the corner with their edge as far
+    // as clearance from both sides of it
+    fn corner_nodes(&self, polygon: &[Point], clearance: f32, height: f32) -> Vec<Node> {
+        let n = polygon.len();
+        let area: f32 = (0..n)
+            .map(|i| {
+                let (a, b) = (&polygon[i], &polygon[(i + 1) % n]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+        let mut nodes = Vec::new();
+        for i in 0..n {
+            let (prev, vertex, next) = (polygon[(i + n - 1) % n], polygon[i], polygon[(i + 1) % n]);
+            let vec_a = (prev.x - vertex.x, prev.y - vertex.y);
+            let vec_b = (next.x - vertex.x, next.y - vertex.y);
+            // the corner turns the same way as the polygon when it is convex
+            let turn = (vertex.x - prev.x) * (next.y - vertex.y)
+                - (vertex.y - prev.y) * (next.x - vertex.x);
+            if turn * area <= 0f32 {
+                continue;
+            }
+            let mag_a = (vec_a.0.powi(2) + vec_a.1.powi(2)).sqrt();
+            let mag_b = (vec_b.0.powi(2) + vec_b.1.powi(2)).sqrt();
+            let bisect = (
+                vec_a.0 / mag_a + vec_b.0 / mag_b,
+                vec_a.1 / mag_a + vec_b.1 / mag_b,
+            );
+            let mag_bisection = (bisect.0.powi(2) + bisect.1.powi(2)).sqrt();
             let theta = ((vec_a.0 * vec_b.0 + vec_a.1 * vec_b.1) / (mag_a * mag_b)).acos();
-            // section direction
-            let (iter_clockwise, straight) = vertex_direction(&vec![a, vertex, b]);
-            // straight line condition
-            if straight == true {
-                println!("Straight!");
+            let d = (self.turning_radius + clearance) / (theta / 2f32).sin();
+            // away from the inside of the corner
+            let center = Point::new(
+                vertex.x - d * bisect.0 / mag_bisection,
+                vertex.y - d * bisect.1 / mag_bisection,
+                0f32,
+            );
+            let height = self.band_height(&center, height);
+            nodes.push(Node::new(center, self.turning_radius, height));
+        }
+        nodes
+    }
+
+    // Nodes to go around a static obstacle, corners of prisms included
+    fn obstacle_node_list(&self, obstacle: &Obstacle) -> Vec<Node> {
+        let buffer = self.obstacle_buffer(obstacle);
+        match obstacle.shape {
+            Shape::Cylinder | Shape::Sphere => {
+                let mut node = Node::from_obstacle(obstacle, &self.origin, buffer);
+                node.height = self.band_height(&node.origin, node.height);
+                vec![node]
+            }
+            Shape::Prism(ref footprint) => {
+                let polygon: Vec<Point> = footprint
+                    .iter()
+                    .map(|location| Point::from_location(location, &self.origin))
+                    .collect();
+                self.corner_nodes(&polygon, buffer, obstacle.height + buffer)
+            }
+        }
+    }
+
+    // Fractions of the way from a to b where the segment enters and leaves the footprint of a
+    // prism, none if it stays outside
+    pub fn prism_crossing(
+        &self,
+        a: &Point,
+        b: &Point,
+        footprint: &[Location],
+    ) -> Option<(f32, f32)> {
+        let polygon: Vec<Point> = footprint
+            .iter()
+            .map(|location| Point::from_location(location, &self.origin))
+            .collect();
+        let mut crossings = Vec::new();
+        if inside(a, &polygon) {
+            crossings.push(0f32);
+        }
+        if inside(b, &polygon) {
+            crossings.push(1f32);
+        }
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        for i in 0..polygon.len() {
+            let (c, d) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+            let (ex, ey) = (d.x - c.x, d.y - c.y);
+            let denom = dx * ey - dy * ex;
+            if denom == 0f32 {
+                continue;
+            }
+            let t = ((c.x - a.x) * ey - (c.y - a.y) * ex) / denom;
+            let u = ((c.x - a.x) * dy - (c.y - a.y) * dx) / denom;
+            if (0f32..=1f32).contains(&t) && (0f32..=1f32).contains(&u) {
+                crossings.push(t);
+            }
+        }
+        let entry = crossings.iter().cloned().fold(f32::MAX, f32::min);
+        let exit = crossings.iter().cloned().fold(f32::MIN, f32::max);
+        // only touching a corner or an edge is not crossing
+        if exit - entry > 0.0001 {
+            Some((entry, exit))
+        } else {
+            None
+        }
+    }
+
+    // Check the segment from a to b against a floating obstacle grown by its buffer, in 3D so
+    // that passing under or over it is clear
+    pub fn floating_intersect(&self, a: &Point, b: &Point, obstacle: &Obstacle) -> bool {
+        let buffer = self.obstacle_buffer(obstacle);
+        let radius = obstacle.radius + buffer;
+        let mut center = Point::from_location(&obstacle.location, &self.origin);
+        if let Shape::Sphere = obstacle.shape {
+            center.z = (obstacle.base + obstacle.height) / 2f32;
+            let ab = (b.x - a.x, b.y - a.y, b.z - a.z);
+            let length_squared = ab.0.powi(2) + ab.1.powi(2) + ab.2.powi(2);
+            let t = if length_squared > 0f32 {
+                let ac = (center.x - a.x, center.y - a.y, center.z - a.z);
+                ((ac.0 * ab.0 + ac.1 * ab.1 + ac.2 * ab.2) / length_squared).clamp(0f32, 1f32)
             } else {
-                let d = if (iter_clockwise == false && direction == 1)
-                    || (iter_clockwise == true && direction == -1)
-                {
-                    TURNING_RADIUS
-                } else {
-                    TURNING_RADIUS / ((theta / 2f32).sin())
-                };
+                0f32
+            };
+            let closest = Point::new(a.x + ab.0 * t, a.y + ab.1 * t, a.z + ab.2 * t);
+            return closest.distance3d(&center) < radius - 0.001;
+        }
+        // altitudes of the segment where it is over the circle
+        let (x, y, dist_squared, _) = intersect_distance(a, b, &center);
+        if dist_squared >= (radius - 0.001).powi(2) {
+            return false;
+        }
+        let length = a.distance(b);
+        let closest = a.distance(&Point::new(x, y, 0f32));
+        let half = (radius.powi(2) - dist_squared).sqrt();
+        let z = |s: f32| {
+            if length > 0f32 {
+                a.z + (b.z - a.z) * s.max(0f32).min(length) / length
+            } else {
+                a.z
+            }
+        };
+        let (entry, exit) = (z(closest - half), z(closest + half));
+        entry.min(exit) < obstacle.height + buffer && entry.max(exit) > obstacle.base - buffer
+    }
 
-                if d > mag_a || d > mag_b {
-                    println!("small angle");
-                } else {
-                    // normal angle node
-                    let dis = d;
-                    let center = Point::new(
-                        dis * bisection.0 + vertex.x,
-                        dis * bisection.1 + vertex.y,
-                        0f32,
-                    );
-                    println!("center: {:?}", center);
-                    let virt_ob = Node::new(center, TURNING_RADIUS, 0f32);
-                    self.nodes.push(Rc::new(RefCell::new(virt_ob)));
+    // Points at the height of obstacle where the segment from a to b enters and leaves it
+    fn obstacle_crossing(
+        &self,
+        a: &Point,
+        b: &Point,
+        obstacle: &Obstacle,
+    ) -> (Option<Point>, Option<Point>) {
+        match obstacle.shape {
+            Shape::Cylinder | Shape::Sphere => self.perpendicular_intersect(a, b, obstacle),
+            Shape::Prism(ref footprint) => match self.prism_crossing(a, b, footprint) {
+                Some((entry, exit)) => {
+                    let height = obstacle.height + self.obstacle_buffer(obstacle);
+                    let at =
+                        |t: f32| Point::new(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t, height);
+                    (Some(at(entry)), Some(at(exit)))
                 }
-            }
-            iter += direction;
+                None => (None, None),
+            },
         }
     }
 
+    // Exclusion polygons and holes of every flyzone, none of which may be flown through
+    pub fn exclusion_polygons(&self) -> &[Vec<Point>] {
+        &self.exclusion_points
+    }
+
+    // Project the flyzones and exclusions from origin once for the many checks against them
+    fn project_areas(&mut self) {
+        let origin = self.origin;
+        let project = |polygon: &Vec<Location>| -> Vec<Point> {
+            polygon
+                .iter()
+                .map(|location| Point::from_location(location, &origin))
+                .collect()
+        };
+        self.boundaries = self
+            .flyzones
+            .iter()
+            .map(|flyzone| project(&flyzone.boundary))
+            .collect();
+        let holes = self
+            .flyzones
+            .iter()
+            .flat_map(|flyzone| flyzone.holes.iter());
+        self.exclusion_points = holes.chain(self.exclusions.iter()).map(project).collect();
+    }
+
     fn find_origin(&mut self) {
         const MAX_RADIAN: f64 = 2f64 * ::std::f64::consts::PI;
         let mut min_lat = MAX_RADIAN;
@@ -230,14 +505,9 @@ impl Pathfinder {
         let mut max_lon = 0f64;
         let mut lon = min_lon;
 
-        assert!(self.flyzones.len() > 0, "Require at least one flyzone");
+        // flyzones are validated before the graph is built
         for i in 0..self.flyzones.len() {
-            let flyzone_points = &self.flyzones[i];
-            assert!(
-                flyzone_points.len() > 2,
-                "Require at least 3 points to construct fly zone."
-            );
-
+            let flyzone_points = &self.flyzones[i].boundary;
             for point in flyzone_points {
                 if point.lat() < min_lat {
                     min_lat = point.lat();
@@ -259,52 +529,31 @@ impl Pathfinder {
     }
 
     // determines vertices of node and flyzone intersection
-    fn sentinel_normal(&mut self, node: &mut Node) -> () {
-        let center: Point = node.origin;
-        let r: f32 = node.radius;
+    fn sentinel_normal(&mut self, node: NodeId) {
+        let center: Point = self.graph.node(node).origin;
+        let r: f32 = self.graph.node(node).radius;
         for flyzone in self.flyzones.iter() {
-            let size = flyzone.len() + 1;
+            let flyzone = &flyzone.boundary;
+            let size = flyzone.len();
             // iterate node over all vertices
             for i in 0..size {
-                let mut v1 = flyzone[i];
-                let mut v2 = flyzone[(i + 1) % size];
+                let v1 = flyzone[i];
+                let v2 = flyzone[(i + 1) % size];
                 let (x, y, dist, end) = intersect_distance(
                     &Point::from_location(&v1, &self.origin),
                     &Point::from_location(&v2, &self.origin),
                     &center,
                 );
                 // check intersect is true
+                let dist = dist.sqrt();
                 if dist > r {
                     continue;
                 }
-                // determine both intersect angles in left and right ring
+                // both intersections are marked in the left and right ring
                 let theta = (dist / r).acos();
-                let dx = x - center.x;
-                let dy = y - center.y;
-                let phi = dy.atan2(dx);
-                let (left, right) = if dy > 0f32 {
-                    (phi, -2f32 * PI + phi)
-                } else {
-                    (2f32 * PI + phi, phi)
-                };
-                let angles = vec![(left, theta), (right, theta)];
-                // create and impliment vertices
-                for (i, j) in angles {
-                    let a = i + j;
-                    let b = i - j;
-                    let vertex_a = Rc::new(RefCell::new(Vertex::new_sentinel(
-                        &mut self.num_vertices,
-                        node.origin,
-                        a,
-                    )));
-                    let vertex_b = Rc::new(RefCell::new(Vertex::new_sentinel(
-                        &mut self.num_vertices,
-                        node.origin,
-                        b,
-                    )));
-                    node.insert_vertex(vertex_a);
-                    node.insert_vertex(vertex_b);
-                }
+                let phi = (y - center.y).atan2(x - center.x);
+                self.graph.add_sentinel(node, phi + theta, None);
+                self.graph.add_sentinel(node, phi - theta, None);
             }
         }
     }
@@ -312,6 +561,7 @@ impl Pathfinder {
     // Generate all valid possible path (tangent lines) between two nodes, and return the
     // shortest valid path if one exists
 
+    // Angles are relative to the line from the center of a to the center of b
     // returns: (i, j, distance, threshold), (a_sentinels, b_sentinels)
     pub fn find_path(
         &self,
@@ -323,12 +573,9 @@ impl Pathfinder {
         let r1: f32 = a.radius;
         let r2: f32 = b.radius;
         let dist: f32 = c1.distance(&c2);
-        // println!(
-        //     "finding path between {:?} and {:?} w/ distance {}",
-        //     c1, c2, dist
-        // );
+        let theta0 = (c2.y - c1.y).atan2(c2.x - c1.x);
 
-        let theta1 = ((r2 - r1).abs() / dist).acos();
+        let theta1 = ((r1 - r2) / dist).acos();
         let theta2 = -theta1;
         let phi1 = theta1;
         let phi2 = -phi1;
@@ -338,7 +585,15 @@ impl Pathfinder {
         let phi4 = -phi3;
         let candidates;
         let mut sentinels = None;
-        if r1 != 0f32 && r2 != 0f32 && dist > r1 + r2 {
+        if dist <= (r1 - r2).abs() {
+            // one node is inside the other, no tangent exists
+            return (Vec::new(), None);
+        } else if r1 == 0f32 && r2 == 0f32 {
+            candidates = vec![(theta1, phi1)];
+        } else if r1 == 0f32 || r2 == 0f32 {
+            // inner and outer tangents are the same for a point
+            candidates = vec![(theta1, phi1), (theta2, phi2)];
+        } else if dist > r1 + r2 {
             candidates = vec![
                 (theta1, phi1),
                 (theta2, phi2),
@@ -350,10 +605,6 @@ impl Pathfinder {
             //determine angle locations of sentinels
             let theta_s = ((r1.powi(2) + dist.powi(2) - r2.powi(2)) / (2f32 * r1 * dist)).acos();
             let phi_s = ((r2.powi(2) + dist.powi(2) - r1.powi(2)) / (2f32 * r2 * dist)).acos();
-            println!(
-                "Generating Sentinels: Theta = {:?}, Phi = {:?}",
-                theta_s, phi_s
-            );
             //sentinel vertices on A
             let a_s1 = theta_s;
             let a_s2 = -theta_s;
@@ -365,87 +616,82 @@ impl Pathfinder {
             let b_s3 = -PI + phi_s;
             let b_s4 = -PI - phi_s;
             sentinels = Some(vec![(a_s1, b_s1), (a_s2, b_s2), (a_s3, b_s3), (a_s4, b_s4)]);
-            println!("{:?}", sentinels)
         }
 
         let mut connections = Vec::new();
-        let mut point_connections = Vec::new();
         for (i, j) in candidates.iter() {
-            //          let p1 = a.to_point(*j + theta0);
-            //			println!("{} {:?}", j, &p1);
-            //          let p2 = b.to_point(*i + theta0);
-            //			println!("{} {:?}", i, &p2);
-            //          match self.valid_path(&p1, &p2) {
-            let p1;
-            let p2;
-            if c1.x > c2.x || c1.y > c2.y {
-                p1 = a.to_point(PI - *i);
-                p2 = b.to_point(PI - *j);
-            } else {
-                p1 = a.to_point(*i);
-                p2 = b.to_point(*j);
-            }
-            // println!(
-            //     "finding distance between {:?} with angle {:?} and {:?} with angle {:?}",
-            //     p1, i, p2, j
-            // );
-            //            if self.valid_path(&p1, &p2) {
-            //probably want to push points in this case later
+            let p1 = a.to_point(theta0 + *i);
+            let p2 = b.to_point(theta0 + *j);
             match self.valid_path(&p1, &p2) {
                 PathValidity::Valid => {
                     connections.push((*i, *j, p1.distance(&p2), 0f32));
-                    point_connections.push((p1, p2));
                 }
                 PathValidity::Flyover(h_min) => {
                     connections.push((*i, *j, p1.distance(&p2), h_min));
-                    point_connections.push((p1, p2));
-                }
-                _ => {
-                    // println!("im sad");
                 }
+                _ => {}
             }
         }
         (connections, sentinels)
     }
 
     // check if a path is valid (not blocked by flightzone or obstacles)
-    fn valid_path(&self, a: &Point, b: &Point) -> PathValidity {
+    pub fn valid_path(&self, a: &Point, b: &Point) -> PathValidity {
         let theta_o = (b.z - a.z).atan2(a.distance(b));
         //check if angle of waypoints is valid
-        if theta_o > MAX_ANGLE_ASCENT {
-            return PathValidity::Invalid;
+        if theta_o > self.max_angle_ascent || theta_o < self.max_angle_descent {
+            return self.reject(a, b, Rejection::ClimbAngle);
+        }
+        // every segment ends inside the altitudes of the flyzones around its end; the only start
+        // outside of them is the plane itself, climbing or descending into them
+        let (min_alt, max_alt) = self.altitude_band(b);
+        if b.z < min_alt - 0.01 || b.z > max_alt + 0.01 {
+            return self.reject(a, b, Rejection::Altitude);
         }
         // println!("validating path: {:?}, {:?}", a, b);
         // latitude is y, longitude is x
         // flyzone is array connected by each index
         // some messy code to link flyzone points, can definitely be better
-        for flyzone in &self.flyzones {
-            let mut tempzone = flyzone.clone();
-            let first = Point::from_location(&tempzone.remove(0), &self.origin);
-            let mut temp = first;
-            for location in tempzone {
-                //println!("origin: {:?}", &self.origin);
-                let point = Point::from_location(&location, &self.origin);
+        for boundary in &self.boundaries {
+            let mut temp = boundary[0];
+            for point in &boundary[1..] {
                 //println!("test intersect for {:?} {:?} {:?} {:?}", a, b, &temp, &point);
-                if intersect(a, b, &temp, &point) {
+                if intersect(a, b, &temp, point) {
                     //println!("false due to flyzone");
-                    return PathValidity::Invalid;
+                    return self.reject(a, b, Rejection::Flyzone);
                 }
-                temp = point;
+                temp = *point;
             }
-            //println!("test intersect for {:?} {:?} {:?} {:?}", a, b, &temp, &first);
-            if intersect(a, b, &temp, &first) {
+            //println!("test intersect for {:?} {:?} {:?} {:?}", a, b, &temp, &boundary[0]);
+            if intersect(a, b, &temp, &boundary[0]) {
                 //println!("false due to flyzone");
-                return PathValidity::Invalid;
+                return self.reject(a, b, Rejection::Flyzone);
             }
         }
 
-        // test for obstacles
-        for obstacle in &self.obstacles {
+        for polygon in self.exclusion_polygons() {
+            let n = polygon.len();
+            let crosses = (0..n).any(|i| intersect(a, b, &polygon[i], &polygon[(i + 1) % n]));
+            if crosses || inside(a, polygon) {
+                return self.reject(a, b, Rejection::Exclusion);
+            }
+        }
+
+        // test for obstacles, moving ones are checked at the time the plane gets there
+        // a segment flies over only if every obstacle it crosses lets it, as high as the highest
+        let mut flyover = None;
+        for obstacle in self.obstacles.iter().filter(|o| !o.is_moving()) {
+            if obstacle.is_floating() {
+                if self.floating_intersect(a, b, obstacle) {
+                    return self.reject(a, b, Rejection::Obstacle);
+                }
+                continue;
+            }
+            let height = obstacle.height + self.obstacle_buffer(obstacle);
+            let obstacle_point = Point::from_location(&obstacle.location, &self.origin);
             // catch the simple cases for now: if a or b are inside the radius of obstacle, invalid
             // check if there are two points of intersect, for flyover cases
-            if let (Some(p1), Some(p2)) = self.perpendicular_intersect(a, b, obstacle) {
-                println!("p1:{:?}, p2:{:?}", p1, p2);
+            if let (Some(p1), Some(p2)) = self.obstacle_crossing(a, b, obstacle) {
                 let theta1 =
                 //if a.z > b.z {
                 //   (p2.z - a.z).atan2(a.distance(&p2))
@@ -461,16 +707,55 @@ impl Pathfinder {
 					(ah, bh) if ah < bh =>	(p1.z - a.z).atan2(a.distance(&p1)),
 					_ => 0f32
 				};
-                if theta1 == 0f32 && a.z < obstacle.height {
-                    return PathValidity::Invalid;
+                if theta1 == 0f32 && a.z < height {
+                    return self.reject(a, b, Rejection::Obstacle);
                 } else if theta_o < theta1 {
-                    return PathValidity::Invalid;
+                    return self.reject(a, b, Rejection::Obstacle);
+                } else if height > self.altitude_band(&obstacle_point).1 {
+                    return self.reject(a, b, Rejection::Ceiling);
                 } else {
-                    return PathValidity::Flyover(obstacle.height);
+                    flyover = match flyover {
+                        Some(h_max) if h_max > height => Some(h_max),
+                        _ => Some(height),
+                    };
                 }
             }
         }
-        PathValidity::Valid
+        match flyover {
+            Some(height) => PathValidity::Flyover(height),
+            None => PathValidity::Valid,
+        }
+    }
+
+    // Lowest floor and highest ceiling of the flyzones around point, unbounded outside of them
+    pub fn altitude_band(&self, point: &Point) -> (f32, f32) {
+        let mut band = None;
+        for (flyzone, boundary) in self.flyzones.iter().zip(&self.boundaries) {
+            if inside(point, boundary) {
+                let (min_alt, max_alt) = band.unwrap_or((f32::MAX, f32::MIN));
+                band = Some((min_alt.min(flyzone.min_alt), max_alt.max(flyzone.max_alt)));
+            }
+        }
+        band.unwrap_or((f32::MIN, f32::MAX))
+    }
+
+    // Height moved into the altitudes allowed at point, for nodes whose height the plane flies
+    // their tangents at
+    pub fn band_height(&self, point: &Point, height: f32) -> f32 {
+        let (min_alt, max_alt) = self.altitude_band(point);
+        height.max(min_alt).min(max_alt)
+    }
+
+    // Margin kept around obstacle, its own buffer if it has one
+    pub fn obstacle_buffer(&self, obstacle: &Obstacle) -> f32 {
+        obstacle.buffer.unwrap_or(self.buffer)
+    }
+
+    // Notify the observer that the path from a to b is invalid
+    fn reject(&self, a: &Point, b: &Point, reason: Rejection) -> PathValidity {
+        let (from, to) = (a.to_location(&self.origin), b.to_location(&self.origin));
+        self.observer.edge_rejected(&from, &to, reason);
+        PathValidity::Invalid
     }
 
     // temporary placeholder function to test functionality of point determination
@@ -482,23 +767,26 @@ impl Pathfinder {
     ) -> (Option<Point>, Option<Point>) {
         // intersect distance gives x and y of intersect point, then distance
         // calculates the shortest distance between the segment and obstacle. If less than radius, it intersects.
+        let buffer = self.obstacle_buffer(c);
+        let (radius, height) = (c.radius + buffer, c.height + buffer);
         let (x, y, distance, endpoint) =
             intersect_distance(a, b, &Point::from_location(&c.location, &self.origin));
-        if distance.sqrt() < c.radius as f32 {
+        // tangents touch the grown obstacle, rounding must not make them cross it
+        if distance.sqrt() < radius - 0.001 {
             // immediately check if the endpoint is the shortest distance; can't fly over in this case
             // EXCEPTION: endpoint is inside obstacle but still generates a perpendicular.
             // if endpoint {
             //     // not technically none, but should be considered as such as we will stop calculations
             //     return (None, None);
             // }
-            let mag = (c.radius.powi(2) - distance).sqrt();
+            let mag = (radius.powi(2) - distance).sqrt();
             //println!("mag: {}", mag);
             //calculate unit vectors for y and x directions
             let dx = (a.x - b.x) / a.distance(b);
             let dy = (a.y - b.y) / a.distance(b);
 
-            let p1 = Point::new(x + dx * mag, y + dy * mag, c.height);
-            let p2 = Point::new(x - dx * mag, y - dy * mag, c.height);
+            let p1 = Point::new(x + dx * mag, y + dy * mag, height);
+            let p2 = Point::new(x - dx * mag, y - dy * mag, height);
             return (Some(p1), Some(p2));
         } else {
             return (None, None);
@@ -514,8 +802,9 @@ impl Pathfinder {
     ) -> (Option<Point>, Option<Point>) {
         //y = mx + b for point a and b
 
+        let buffer = self.obstacle_buffer(obstacle);
         let mut c = Point::from_location(&obstacle.location, &self.origin);
-        c.z = obstacle.height;
+        c.z = obstacle.height + buffer;
         let dx = b.x - a.x;
         let dy = b.y - a.y;
 
@@ -538,7 +827,7 @@ impl Pathfinder {
         let quad_b = 2.0 * (slope * slope_intercept - slope * dep - indep);
         let quad_c = indep.powi(2) + dep.powi(2) + slope_intercept.powi(2)
             - 2.0 * slope_intercept * dep
-            - obstacle.radius.powi(2);
+            - (obstacle.radius + buffer).powi(2);
 
         //Check discriminant (if > 0, 2 intersects; if = 0, 1 intersect; if < 0, no intersects)
         let discriminant = quad_b.powi(2) - 4.0 * quad_a * quad_c;
@@ -0,0 +1,127 @@
+// Moving obstacles, checked against where they will be when the plane gets near them
+
+use super::*;
+
+impl Pathfinder {
+    // Where obstacle will be t seconds from now, following its track and then its velocity
+    pub fn obstacle_position(&self, obstacle: &Obstacle, t: f32) -> Point {
+        let mut prev = (0f32, Point::from_location(&obstacle.location, &self.origin));
+        for &(time, ref location) in &obstacle.track {
+            let next = Point::from_location(location, &self.origin);
+            if t <= time {
+                let s = if time > prev.0 {
+                    (t - prev.0) / (time - prev.0)
+                } else {
+                    1f32
+                };
+                return Point::new(
+                    prev.1.x + (next.x - prev.1.x) * s,
+                    prev.1.y + (next.y - prev.1.y) * s,
+                    prev.1.z + (next.z - prev.1.z) * s,
+                );
+            }
+            prev = (time, next);
+        }
+        let (time, point) = prev;
+        Point::new(
+            point.x + obstacle.velocity.0 * (t - time),
+            point.y + obstacle.velocity.1 * (t - time),
+            point.z,
+        )
+    }
+
+    // Node around a moving obstacle where the plane, leaving start at depart, would reach it
+    pub fn predicted_node(&self, obstacle: &Obstacle, start: &Point, depart: f32) -> Node {
+        // a few rounds converge as long as the obstacle is slower than the plane
+        let mut t = depart;
+        for _ in 0..3 {
+            let center = self.obstacle_position(obstacle, t);
+            t = depart + start.distance(&center) / self.speed;
+        }
+        let center = self.obstacle_position(obstacle, t);
+        // grow the node by how far the obstacle moves while the plane passes it
+        let buffer = self.obstacle_buffer(obstacle);
+        let radius = obstacle.radius + buffer;
+        let passing = 2f32 * radius / self.speed;
+        let drift = center.distance(&self.obstacle_position(obstacle, t + passing));
+        Node::new(center, radius + drift, obstacle.height + buffer)
+    }
+
+    // check if a path left at time depart stays clear of moving obstacles
+    pub fn valid_path_at(&self, a: &Point, b: &Point, depart: f32) -> PathValidity {
+        let duration = a.distance(b) / self.speed;
+        let plane = |t: f32| {
+            let s = if duration > 0f32 {
+                (t - depart) / duration
+            } else {
+                0f32
+            };
+            Point::new(a.x + (b.x - a.x) * s, a.y + (b.y - a.y) * s, a.z)
+        };
+        // moving obstacles are avoided at any altitude they reach
+        for obstacle in self.obstacles.iter().filter(|o| o.is_moving()) {
+            let buffer = self.obstacle_buffer(obstacle);
+            let radius = obstacle.radius + buffer;
+            if obstacle.is_floating()
+                && (a.z.max(b.z) < obstacle.base - buffer
+                    || a.z.min(b.z) > obstacle.height + buffer)
+            {
+                continue;
+            }
+            // the obstacle moves steadily between points of its track
+            let mut times = vec![depart];
+            times.extend(
+                obstacle
+                    .track
+                    .iter()
+                    .map(|&(t, _)| t)
+                    .filter(|&t| t > depart && t < depart + duration),
+            );
+            times.push(depart + duration);
+            for span in times.windows(2) {
+                let c = self.obstacle_position(obstacle, span[0]);
+                let d = self.obstacle_position(obstacle, span[1]);
+                let distance = closest_approach(&plane(span[0]), &plane(span[1]), &c, &d);
+                if distance < radius {
+                    return self.reject(a, b, Rejection::MovingObstacle);
+                }
+            }
+        }
+        PathValidity::Valid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn moving_obstacle_test() {
+        let mut pathfinder = PathfinderBuilder::new()
+            .speed(20f32)
+            .create(square_flyzone(), Vec::new())
+            .unwrap();
+        // clear of the direct path now, but crossing it when the plane gets there
+        let crossing =
+            Obstacle::new(at(200f32, 50f32, 200f32), 30f32, 200f32).velocity(0f32, 20f32);
+        pathfinder.set_obstacles(vec![crossing.clone()]);
+        let points = cross_square(&mut pathfinder, 50f32);
+        assert!(points.len() > 2);
+        // fly the path and stay clear of the obstacle the whole time
+        let mut time = 0f32;
+        for pair in points.windows(2) {
+            let arrive = time + pair[0].distance(&pair[1]) / 20f32;
+            let c = pathfinder.obstacle_position(&crossing, time);
+            let d = pathfinder.obstacle_position(&crossing, arrive);
+            assert!(closest_approach(&pair[0], &pair[1], &c, &d) > 29f32);
+            time = arrive;
+        }
+
+        // in the way now, but long gone when the plane gets there
+        let leaving =
+            Obstacle::new(at(200f32, 200f32, 200f32), 30f32, 200f32).velocity(0f32, 40f32);
+        pathfinder.set_obstacles(vec![leaving]);
+        assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+    }
+}
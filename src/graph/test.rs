@@ -1,5 +1,7 @@
 use super::*;
+use fixture::*;
 use obj::Obstacle;
+use std::rc::Rc;
 const THRESHOLD: f64 = 0.001;
 
 //assert equal, equal practically because floating points suck for intersection
@@ -55,12 +57,14 @@ fn dummy_origin() -> Location {
 }
 
 // Helper function to create an obstacle based on its position in transformed graph
+// Without a buffer, so that geometry is checked against the obstacle itself
 fn obstacle_from_meters(x: f32, y: f32, radius: f32, height: f32) -> Obstacle {
     Obstacle::new(
         Location::from_meters(x, y, height, &dummy_origin()),
         radius,
         height,
     )
+    .buffer(0f32)
 }
 
 fn dummy_flyzones() -> Vec<Vec<Location>> {
@@ -165,6 +169,27 @@ fn obstacles_pathing() {
     assert_eq!(bool::from(pathfinder.valid_path(&c, &e)), false);
 }
 
+#[test]
+fn climb_angle_pathing() {
+    let a = Point::new(20f32, 20f32, 10f32);
+    let b = Point::new(30f32, 20f32, 25f32);
+    let c = Point::new(30f32, 20f32, 30f32);
+
+    let pathfinder = dummy_pathfinder();
+    assert!(bool::from(pathfinder.valid_path(&a, &b)));
+    assert!(!bool::from(pathfinder.valid_path(&a, &c)));
+    assert!(bool::from(pathfinder.valid_path(&b, &a)));
+    assert!(!bool::from(pathfinder.valid_path(&c, &a)));
+
+    let pathfinder = PathfinderBuilder::new()
+        .max_angle_ascent(PI / 4f32)
+        .max_angle_descent(-PI / 2f32)
+        .create(dummy_flyzones(), Vec::new())
+        .unwrap();
+    assert!(!bool::from(pathfinder.valid_path(&a, &b)));
+    assert!(bool::from(pathfinder.valid_path(&c, &a)));
+}
+
 #[test]
 fn intersects_circle() {
     //Desmos Visual: https://www.desmos.com/calculator/fxknkpinao
@@ -572,12 +597,12 @@ fn virtualize_flyzone_square() {
     let expected = vec![node_d, node_c, node_b, node_a];
     let test_flyzone = vec![vec![d, c, b, a]];
     for i in 0..4 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
     let test_flyzone = vec![vec![a, b, c, d]];
     pathfinder.set_flyzone(test_flyzone);
     for i in 0..4 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
 }
 
@@ -631,12 +656,12 @@ fn virtualize_flyzone_plus() {
         node_a,
     ];
     for i in 0..11 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
     let test_flyzone = vec![vec![a, b, c, d, e, f, g, h, i, j, k, l]];
     pathfinder.set_flyzone(test_flyzone);
     for i in 0..4 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
 }
 
@@ -656,12 +681,12 @@ fn virtualize_flyzone_linear() {
     let node_d = Point::new(5f32, 15f32, 0f32);
     let expected = vec![node_d, node_c, node_b, node_a];
     for i in 0..4 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
     let test_flyzone = vec![vec![a, b, c, d, e]];
     pathfinder.set_flyzone(test_flyzone);
     for i in 0..4 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
 }
 
@@ -685,12 +710,97 @@ fn virtualize_flyzone_small_angle() {
     let node_e = Point::new(6.2927, 14.3550, 0f32);
     let expected = vec![node_f, node_e, node_d, node_c, node_b, node_a];
     for i in 0..6 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
     }
     let test_flyzone = vec![vec![a, b, c, d, e, f, g]];
     pathfinder.set_flyzone(test_flyzone);
     for i in 0..6 {
-        assert_point_eq(&pathfinder.nodes[i].borrow().origin, &expected[i]);
+        assert_point_eq(&pathfinder.graph.nodes[i].origin, &expected[i]);
+    }
+}
+
+#[test]
+fn virtualize_flyzone_short_edges() {
+    // a fence closed on its first point, with a repeated point, a slight kink along its bottom
+    // and a corner cut by an edge shorter than the turning radius
+    let boundary = vec![
+        Point::new(0f32, 0f32, 10f32),
+        Point::new(50f32, 0.05, 10f32),
+        Point::new(100f32, 0f32, 10f32),
+        Point::new(100f32, 0f32, 10f32),
+        Point::new(100f32, 99f32, 10f32),
+        Point::new(99f32, 100f32, 10f32),
+        Point::new(0f32, 100f32, 10f32),
+        Point::new(0f32, 0f32, 10f32),
+    ];
+    let flyzone = points_to_flyzone(boundary.clone());
+    let pathfinder = Pathfinder::create(1f32, vec![flyzone], Vec::new());
+    let inner: Vec<Point> = pathfinder
+        .graph
+        .nodes
+        .iter()
+        .map(|node| node.origin)
+        .collect();
+    assert_eq!(inner.len(), 5);
+    let radius = pathfinder.turning_radius;
+    for origin in &inner {
+        assert!(inside(origin, &boundary));
+        assert!(distance_to_edges(origin, &boundary) > radius - 0.01);
+    }
+    let expected = vec![
+        Point::new(5f32, 5f32, 0f32),
+        Point::new(95f32, 5f32, 0f32),
+        Point::new(5f32, 95f32, 0f32),
+    ];
+    // the nodes beside the kink move in just enough to clear it
+    for point in &expected {
+        assert!(inner.iter().any(|origin| origin.distance(point) < 0.5));
+    }
+    // the cut corner still has room to turn in, next to each end of the short edge
+    let cut = Point::new(99.5, 99.5, 0f32);
+    assert_eq!(
+        inner
+            .iter()
+            .filter(|origin| origin.distance(&cut) < 12f32)
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn virtualize_flyzone_notch() {
+    // a narrow strip of the fence reaching into the flyzone, with edges shorter than the
+    // turning radius across its end
+    let boundary = vec![
+        Point::new(0f32, 0f32, 10f32),
+        Point::new(0f32, 100f32, 10f32),
+        Point::new(49f32, 100f32, 10f32),
+        Point::new(49f32, 60f32, 10f32),
+        Point::new(51f32, 60f32, 10f32),
+        Point::new(51f32, 100f32, 10f32),
+        Point::new(100f32, 100f32, 10f32),
+        Point::new(100f32, 0f32, 10f32),
+    ];
+    for points in [boundary.clone(), boundary.iter().rev().cloned().collect()] {
+        let flyzone = points_to_flyzone(points);
+        let pathfinder = Pathfinder::create(1f32, vec![flyzone], Vec::new());
+        assert_eq!(pathfinder.graph.nodes.len(), 8);
+        // the plane turns in the corners beside the strip and goes around its end, on circles
+        // through the corners at the end
+        let offset = 5f32 / 2f32.sqrt();
+        let expected = vec![
+            Point::new(44f32, 95f32, 0f32),
+            Point::new(56f32, 95f32, 0f32),
+            Point::new(49f32 + offset, 60f32 + offset, 0f32),
+            Point::new(51f32 - offset, 60f32 + offset, 0f32),
+        ];
+        for point in &expected {
+            assert!(pathfinder
+                .graph
+                .nodes
+                .iter()
+                .any(|node| node.origin.distance(point) < 0.001));
+        }
     }
 }
 
@@ -709,3 +819,518 @@ fn sentinel_vertex_test() {
     vertex.angle =
     let expected_vertices = []
 }*/
+
+// Vertices still in a ring, split into tangents and sentinels
+fn count_vertices(graph: &Graph) -> (usize, usize) {
+    let mut count = (0, 0);
+    for node in &graph.nodes {
+        for v in node.left_ring.iter().chain(&node.right_ring) {
+            match graph.vertex(*v).sentinel {
+                false => count.0 += 1,
+                true => count.1 += 1,
+            }
+        }
+    }
+    count
+}
+
+#[test]
+fn incremental_graph() {
+    let obstacles = vec![
+        obstacle_from_meters(100f32, 200f32, 40f32, 10f32),
+        obstacle_from_meters(300f32, 200f32, 40f32, 10f32),
+    ];
+    let mut pathfinder = Pathfinder::create(1f32, dummy_flyzones(), obstacles.clone());
+    let initial = count_vertices(&pathfinder.graph);
+
+    // blocks the tangents between the first two and overlaps the second
+    let blocking = obstacle_from_meters(230f32, 200f32, 40f32, 10f32);
+    let id = pathfinder.add_obstacle(blocking.clone()).unwrap();
+    let mut all = obstacles.clone();
+    all.push(blocking);
+    let rebuilt = Pathfinder::create(1f32, dummy_flyzones(), all);
+    assert_eq!(
+        count_vertices(&pathfinder.graph),
+        count_vertices(&rebuilt.graph)
+    );
+    assert!(count_vertices(&pathfinder.graph).1 > initial.1);
+
+    pathfinder.remove_obstacle(id).unwrap();
+    assert!(pathfinder.graph.nodes[pathfinder.graph.nodes.len() - 1].removed);
+    assert_eq!(count_vertices(&pathfinder.graph), initial);
+}
+
+#[test]
+fn reused_slots() {
+    let obstacles = vec![
+        obstacle_from_meters(100f32, 200f32, 40f32, 10f32),
+        obstacle_from_meters(300f32, 200f32, 40f32, 10f32),
+    ];
+    let mut pathfinder = Pathfinder::create(1f32, dummy_flyzones(), obstacles);
+    let blocking = obstacle_from_meters(230f32, 200f32, 40f32, 10f32);
+    let id = pathfinder.add_obstacle(blocking.clone()).unwrap();
+    pathfinder.remove_obstacle(id).unwrap();
+    let size = (
+        pathfinder.graph.nodes.len(),
+        pathfinder.graph.vertices.len(),
+    );
+
+    // the same obstacle coming and going keeps taking the slots it freed
+    for _ in 0..10 {
+        let id = pathfinder.add_obstacle(blocking.clone()).unwrap();
+        pathfinder.remove_obstacle(id).unwrap();
+    }
+    assert_eq!(
+        (
+            pathfinder.graph.nodes.len(),
+            pathfinder.graph.vertices.len()
+        ),
+        size
+    );
+    // no search is kept to read the changes
+    assert!(pathfinder.graph.changes.is_empty());
+}
+
+#[test]
+fn exclusion_nodes() {
+    let mut pathfinder = dummy_pathfinder();
+    let initial = pathfinder.graph.nodes.len();
+    // L shaped, with a concave corner at (150, 150)
+    let mut points = vec![
+        Point::new(100f32, 100f32, 10f32),
+        Point::new(200f32, 100f32, 10f32),
+        Point::new(200f32, 150f32, 10f32),
+        Point::new(150f32, 150f32, 10f32),
+        Point::new(150f32, 200f32, 10f32),
+        Point::new(100f32, 200f32, 10f32),
+    ];
+    for _ in 0..2 {
+        pathfinder.set_exclusions(vec![points_to_flyzone(points.clone())]);
+        assert_eq!(pathfinder.graph.nodes.len(), initial + 5);
+        let polygon = &pathfinder.exclusion_polygons()[0];
+        let clearance = pathfinder.turning_radius + pathfinder.buffer;
+        for node in &pathfinder.graph.nodes[initial..] {
+            assert!(!inside(&node.origin, polygon));
+            for i in 0..polygon.len() {
+                let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+                let (_, _, dist, _) = intersect_distance(a, b, &node.origin);
+                assert!(dist.sqrt() > clearance - 0.01);
+            }
+        }
+        // either way around
+        points.reverse();
+    }
+}
+
+#[test]
+fn prism_nodes() {
+    // a triangle has three convex corners
+    let footprint = points_to_flyzone(vec![
+        Point::new(150f32, 150f32, 0f32),
+        Point::new(250f32, 150f32, 0f32),
+        Point::new(200f32, 250f32, 0f32),
+    ]);
+    let prism = Obstacle::prism(footprint, 30f32).buffer(0f32);
+    let mut pathfinder = dummy_pathfinder();
+    let initial = (
+        pathfinder.graph.nodes.len(),
+        count_vertices(&pathfinder.graph),
+    );
+    let id = pathfinder.add_obstacle(prism.clone()).unwrap();
+    let rebuilt = Pathfinder::create(1f32, dummy_flyzones(), vec![prism]);
+    assert_eq!(pathfinder.graph.nodes.len(), initial.0 + 3);
+    assert_eq!(
+        count_vertices(&pathfinder.graph),
+        count_vertices(&rebuilt.graph)
+    );
+    pathfinder.remove_obstacle(id).unwrap();
+    assert_eq!(count_vertices(&pathfinder.graph), initial.1);
+}
+
+#[test]
+fn flyzone_altitude() {
+    let flyzone = |min_alt: f32, max_alt: f32| {
+        let boundary = square_flyzone().remove(0);
+        vec![Flyzone::new(boundary).altitude(min_alt, max_alt)]
+    };
+    assert_eq!(
+        Pathfinder::try_create(1f32, flyzone(120f32, 30f32), Vec::new()).err(),
+        Some(PathfinderError::InvalidParameter {
+            name: "max_alt",
+            value: 30f32
+        })
+    );
+    let obstacle = center_obstacle(50f32, 200f32);
+    let mut pathfinder = Pathfinder::create(1f32, flyzone(30f32, 120f32), vec![obstacle.clone()]);
+    let waypoint = |alt: f32| {
+        let (plane, mut waypoints) = crossing(10f32, alt);
+        waypoints.front_mut().unwrap().index = 4;
+        (plane, waypoints)
+    };
+    let (plane, waypoints) = waypoint(150f32);
+    assert_eq!(
+        pathfinder.try_get_adjust_path(plane, waypoints).err(),
+        Some(PathfinderError::WaypointAltitude {
+            waypoint: 4,
+            alt: 150f32
+        })
+    );
+    // the plane climbs into the band as it goes around the obstacle
+    let (plane, waypoints) = waypoint(100f32);
+    let path = pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+    assert!(path.len() > 1);
+    for wp in path {
+        assert!(wp.location.alt() >= 30f32 && wp.location.alt() <= 120f32);
+    }
+
+    // the obstacle can be flown over only without a ceiling
+    let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![obstacle]);
+    let a = project(&pathfinder, &at(50f32, 200f32, 250f32));
+    let b = project(&pathfinder, &at(350f32, 200f32, 250f32));
+    assert!(matches!(
+        pathfinder.valid_path(&a, &b),
+        PathValidity::Flyover(_)
+    ));
+    pathfinder.set_flyzone(flyzone(30f32, 120f32));
+    assert!(matches!(
+        pathfinder.valid_path(&a, &b),
+        PathValidity::Invalid
+    ));
+
+    // segments end between the floor and the ceiling, starting below the floor is left to the
+    // plane climbing into the band
+    let point = |x: f32, z: f32| project(&pathfinder, &at(x, 50f32, z));
+    assert!(bool::from(
+        pathfinder.valid_path(&point(50f32, 50f32), &point(150f32, 60f32))
+    ));
+    assert!(bool::from(
+        pathfinder.valid_path(&point(50f32, 10f32), &point(150f32, 40f32))
+    ));
+    assert!(!bool::from(
+        pathfinder.valid_path(&point(50f32, 50f32), &point(150f32, 20f32))
+    ));
+    assert!(!bool::from(
+        pathfinder.valid_path(&point(50f32, 100f32), &point(150f32, 130f32))
+    ));
+    // nodes at the corners of the flyzone are at its floor
+    assert!(pathfinder
+        .graph
+        .nodes
+        .iter()
+        .all(|node| node.height >= 30f32));
+}
+
+#[test]
+fn prism_path() {
+    let footprint: Vec<Location> = [
+        (150f32, 180f32),
+        (250f32, 180f32),
+        (250f32, 220f32),
+        (150f32, 220f32),
+    ]
+    .iter()
+    .map(|&(x, y)| at(x, y, 0f32))
+    .collect();
+    let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+    assert_eq!(
+        pathfinder.add_obstacle(Obstacle::prism(footprint[..2].to_vec(), 50f32)),
+        Err(PathfinderError::InvalidParameter {
+            name: "footprint",
+            value: 2f32
+        })
+    );
+    let building = Obstacle::prism(footprint.clone(), 50f32);
+    assert!(building.radius > 53f32);
+    pathfinder.add_obstacle(building).unwrap();
+    let points = cross_square(&mut pathfinder, 30f32);
+    assert!(points.len() > 2);
+    // around the corners, well inside the circle around the footprint
+    let polygon: Vec<Point> = footprint
+        .iter()
+        .map(|location| project(&pathfinder, location))
+        .collect();
+    let middle = project(&pathfinder, &at(200f32, 200f32, 0f32));
+    for pair in points.windows(2) {
+        assert!((pair[1].y - middle.y).abs() < 40f32);
+        for i in 0..4 {
+            assert!(!intersect(
+                &pair[0],
+                &pair[1],
+                &polygon[i],
+                &polygon[(i + 1) % 4]
+            ));
+        }
+    }
+    // high enough, the plane flies straight over it
+    assert_eq!(cross_square(&mut pathfinder, 100f32).len(), 2);
+}
+
+#[test]
+fn floating_path() {
+    let center = at(200f32, 200f32, 150f32);
+    let balloon = Obstacle::sphere(center, 30f32);
+    assert_eq!((balloon.base, balloon.height), (120f32, 180f32));
+    let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+    assert_eq!(
+        pathfinder.add_obstacle(Obstacle::new(center, 30f32, 100f32).base(120f32)),
+        Err(PathfinderError::InvalidParameter {
+            name: "base",
+            value: 120f32
+        })
+    );
+    let id = pathfinder.add_obstacle(balloon.clone()).unwrap();
+    // under, through and over the balloon
+    assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+    let points = cross_square(&mut pathfinder, 150f32);
+    assert!(points.len() > 2);
+    let middle = project(&pathfinder, &center);
+    for pair in points.windows(2) {
+        let (from, to) = (pair[0].distance(&middle), pair[1].distance(&middle));
+        // the plane follows an arc around the balloon between points as far from it
+        assert!(from > 34.99 && to > 34.99);
+        if (from - to).abs() > 0.01 {
+            assert!(!pathfinder.floating_intersect(&pair[0], &pair[1], &balloon));
+        }
+    }
+    assert_eq!(cross_square(&mut pathfinder, 250f32).len(), 2);
+
+    // a cylinder standing on the ground blocks the airspace under it
+    pathfinder.remove_obstacle(id).unwrap();
+    pathfinder
+        .add_obstacle(Obstacle::new(center, 30f32, 180f32))
+        .unwrap();
+    assert!(cross_square(&mut pathfinder, 50f32).len() > 2);
+    pathfinder.set_obstacles(vec![Obstacle::new(center, 30f32, 180f32).base(120f32)]);
+    assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+    assert!(cross_square(&mut pathfinder, 150f32).len() > 2);
+
+    // in 3D, a segment can pass the sphere where the circle around it would be crossed
+    let floating = pathfinder.obstacles[0].clone();
+    let sphere = Obstacle::sphere(center, 30f32);
+    let point = |x: f32, y: f32, z: f32| project(&pathfinder, &at(x, y, z));
+    let (a, b) = (point(150f32, 225f32, 175f32), point(250f32, 225f32, 175f32));
+    assert!(pathfinder.floating_intersect(&a, &b, &floating));
+    assert!(!pathfinder.floating_intersect(&a, &b, &sphere));
+    let (a, b) = (point(170f32, 200f32, 150f32), point(230f32, 200f32, 150f32));
+    assert!(pathfinder.floating_intersect(&a, &b, &sphere));
+}
+
+#[test]
+fn flyover_every_obstacle() {
+    let shed = |x: f32, height: f32| Obstacle::new(at(x, 200f32, height), 20f32, height);
+    let mut pathfinder = Pathfinder::create(
+        1f32,
+        square_flyzone(),
+        vec![shed(120f32, 20f32), shed(280f32, 40f32)],
+    );
+    let a = project(&pathfinder, &at(50f32, 200f32, 100f32));
+    let b = project(&pathfinder, &at(350f32, 200f32, 100f32));
+    // as high as the highest obstacle crossed
+    let buffer = pathfinder.obstacle_buffer(&pathfinder.obstacles[1]);
+    assert!(matches!(
+        pathfinder.valid_path(&a, &b),
+        PathValidity::Flyover(height) if height == 40f32 + buffer
+    ));
+    // a balloon behind the first obstacle is not flown over
+    pathfinder.set_obstacles(vec![
+        shed(120f32, 20f32),
+        Obstacle::sphere(at(280f32, 200f32, 100f32), 20f32),
+    ]);
+    assert!(matches!(
+        pathfinder.valid_path(&a, &b),
+        PathValidity::Invalid
+    ));
+}
+
+#[test]
+fn incremental_obstacle() {
+    let obstacle = |x: f32, y: f32| Obstacle::new(at(x, y, 200f32), 50f32, 200f32);
+    assert_eq!(
+        Pathfinder::new().add_obstacle(obstacle(200f32, 200f32)),
+        Err(PathfinderError::Uninitialized)
+    );
+    let side = obstacle(200f32, 320f32);
+    let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![side.clone()]);
+    let side_id = pathfinder.get_obstacle_ids()[0];
+    assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+
+    let id = pathfinder.add_obstacle(obstacle(200f32, 200f32)).unwrap();
+    assert_ne!(id, side_id);
+    assert_eq!(pathfinder.get_obstacle_ids(), &vec![side_id, id]);
+    let incremental = cross_square(&mut pathfinder, 50f32);
+    let mut rebuilt = Pathfinder::create(
+        1f32,
+        square_flyzone(),
+        pathfinder.get_obstacle_list().clone(),
+    );
+    let expected = cross_square(&mut rebuilt, 50f32);
+    assert!(incremental.len() > 2);
+    assert_eq!(incremental.len(), expected.len());
+    for (a, b) in incremental.iter().zip(&expected) {
+        assert!(a.distance(b) < 0.01);
+    }
+
+    // moved out of the way, the direct path is restored
+    pathfinder
+        .update_obstacle(id, obstacle(200f32, 80f32))
+        .unwrap();
+    assert_eq!(pathfinder.get_obstacle_ids(), &vec![side_id, id]);
+    assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+
+    pathfinder
+        .update_obstacle(id, obstacle(200f32, 200f32))
+        .unwrap();
+    assert!(cross_square(&mut pathfinder, 50f32).len() > 2);
+    assert!(pathfinder.remove_obstacle(id).is_ok());
+    assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+    assert_eq!(
+        pathfinder.remove_obstacle(id).err(),
+        Some(PathfinderError::UnknownObstacle(id))
+    );
+    let removed = pathfinder.remove_obstacle(side_id).unwrap();
+    assert_eq!(removed.location, side.location);
+    assert!(pathfinder.get_obstacle_list().is_empty());
+}
+
+#[test]
+fn obstacle_buffer() {
+    let obstacle = center_obstacle(50f32, 200f32);
+    let mut pathfinder = PathfinderBuilder::new()
+        .buffer(10f32)
+        .create(square_flyzone(), vec![obstacle.clone()])
+        .unwrap();
+    // distances of the points of the path across the obstacle from its center
+    let distances = |pathfinder: &mut Pathfinder| -> Vec<f32> {
+        let (plane, waypoints) = crossing(50f32, 50f32);
+        let origin = pathfinder.origin;
+        let center = project(pathfinder, &at(200f32, 200f32, 200f32));
+        let path = pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+        path.iter()
+            .map(|wp| Point::from_location(&wp.location, &origin).distance(&center))
+            .collect()
+    };
+    let hugs = |distances: &[f32], radius: f32| {
+        distances.iter().all(|&d| d > radius - 0.01)
+            && distances.iter().any(|&d| (d - radius).abs() < 0.01)
+    };
+    assert!(hugs(&distances(&mut pathfinder), 60f32));
+
+    // an obstacle of its own buffer keeps the plane further away
+    let id = pathfinder.get_obstacle_ids()[0];
+    pathfinder
+        .update_obstacle(id, obstacle.clone().buffer(20f32))
+        .unwrap();
+    assert!(hugs(&distances(&mut pathfinder), 70f32));
+    assert_eq!(
+        pathfinder.add_obstacle(obstacle.buffer(-1f32)),
+        Err(PathfinderError::InvalidParameter {
+            name: "buffer",
+            value: -1f32
+        })
+    );
+}
+
+#[test]
+fn flyzone_notch() {
+    // a closed fence with a strip reaching in between the plane and its waypoint, narrower
+    // than the plane turns
+    let boundary: Vec<Point> = vec![
+        (0f32, 0f32),
+        (0f32, 100f32),
+        (49f32, 100f32),
+        (49f32, 40f32),
+        (50f32, 39.5),
+        (51f32, 40f32),
+        (51f32, 100f32),
+        (100f32, 100f32),
+        (100f32, 0f32),
+        (0f32, 0f32),
+    ]
+    .into_iter()
+    .map(|(x, y)| Point::new(x, y, 10f32))
+    .collect();
+    let flyzone: Vec<Location> = boundary
+        .iter()
+        .map(|point| point.to_location(&origin()))
+        .collect();
+    let mut pathfinder = Pathfinder::create(1f32, vec![flyzone], Vec::new());
+    let plane = Plane::new(at(40f32, 80f32, 50f32));
+    let mut waypoints = LinkedList::new();
+    waypoints.push_back(Waypoint::new(0, at(60f32, 80f32, 50f32), 1f32));
+    let path = pathfinder
+        .try_get_adjust_path(plane, waypoints)
+        .unwrap()
+        .clone();
+    assert!(path.len() > 1);
+    let mut prev = project(&pathfinder, &plane.location);
+    for wp in &path {
+        let point = project(&pathfinder, &wp.location);
+        for i in 0..boundary.len() - 1 {
+            assert!(!intersect(&prev, &point, &boundary[i], &boundary[i + 1]));
+        }
+        prev = point;
+    }
+}
+
+#[test]
+fn exclusion_polygon() {
+    let square = |min: f32, max: f32| {
+        vec![
+            at(min, min, 10f32),
+            at(max, min, 10f32),
+            at(max, max, 10f32),
+            at(min, max, 10f32),
+        ]
+    };
+    let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+    assert_eq!(
+        pathfinder.try_set_exclusions(vec![
+            square(150f32, 250f32),
+            square(0f32, 0f32)[..2].to_vec()
+        ]),
+        Err(PathfinderError::InvalidExclusion(1))
+    );
+    assert_eq!(
+        pathfinder.try_set_flyzone(vec![
+            Flyzone::new(square(0f32, 400f32)).hole(square(150f32, 250f32)[..2].to_vec())
+        ]),
+        Err(PathfinderError::InvalidHole {
+            flyzone: 0,
+            hole: 0
+        })
+    );
+    let (plane, waypoints) = crossing(100f32, 100f32);
+    let no_fly = square(150f32, 250f32);
+    let hole = Flyzone::new(square(0f32, 400f32)).hole(no_fly.clone());
+    let with_exclusion = {
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+        pathfinder.set_exclusions(vec![no_fly.clone()]);
+        pathfinder
+    };
+    let with_hole = Pathfinder::create(1f32, vec![hole], Vec::new());
+    for mut pathfinder in [with_exclusion, with_hole] {
+        let polygon: Vec<Point> = no_fly
+            .iter()
+            .map(|location| project(&pathfinder, location))
+            .collect();
+        let path = pathfinder
+            .try_get_adjust_path(plane, waypoints.clone())
+            .unwrap()
+            .clone();
+        // around the polygon instead of through it
+        assert!(path.len() > 1);
+        let mut prev = project(&pathfinder, &plane.location);
+        for wp in &path {
+            let point = project(&pathfinder, &wp.location);
+            for i in 0..polygon.len() {
+                assert!(!intersect(
+                    &prev,
+                    &point,
+                    &polygon[i],
+                    &polygon[(i + 1) % 4]
+                ));
+            }
+            prev = point;
+        }
+    }
+}
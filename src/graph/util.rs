@@ -116,6 +116,101 @@ pub fn vertex_direction(points: &Vec<Point>) -> (bool, bool) {
     }
 }
 
+// Smallest distance between two points moving steadily over the same time, one from a to b
+// and the other from c to d
+pub fn closest_approach(a: &Point, b: &Point, c: &Point, d: &Point) -> f32 {
+    // position and motion of the first point relative to the second
+    let (rx, ry) = (a.x - c.x, a.y - c.y);
+    let (wx, wy) = (b.x - d.x - rx, b.y - d.y - ry);
+    let w2 = wx.powi(2) + wy.powi(2);
+    let s = if w2 > 0f32 {
+        (-(rx * wx + ry * wy) / w2).clamp(0f32, 1f32)
+    } else {
+        0f32
+    };
+    ((rx + wx * s).powi(2) + (ry + wy * s).powi(2)).sqrt()
+}
+
+// true if p is inside polygon, points on the boundary may go either way
+pub fn inside(p: &Point, polygon: &[Point]) -> bool {
+    let mut inside = false;
+    let mut prev = match polygon.last() {
+        Some(prev) => prev,
+        None => return false,
+    };
+    // count the edges crossed by a ray going east from p
+    for cur in polygon {
+        if (cur.y > p.y) != (prev.y > p.y) {
+            let x = cur.x + (p.y - cur.y) * (prev.x - cur.x) / (prev.y - cur.y);
+            if x > p.x {
+                inside = !inside;
+            }
+        }
+        prev = cur;
+    }
+    inside
+}
+
+// Indices of the points of polygon left once points within min_edge of the previous one kept
+// are dropped, the closing point repeating the first one included
+pub fn distinct_points(polygon: &[Point], min_edge: f32) -> Vec<usize> {
+    let mut kept: Vec<usize> = Vec::new();
+    for (i, p) in polygon.iter().enumerate() {
+        let apart = match kept.last() {
+            Some(&last) => polygon[last].distance(p) > min_edge,
+            None => true,
+        };
+        if apart {
+            kept.push(i);
+        }
+    }
+    while kept.len() > 1 && polygon[kept[0]].distance(&polygon[kept[kept.len() - 1]]) <= min_edge {
+        kept.pop();
+    }
+    kept
+}
+
+// Polygon without repeated points and without corners turning less than tolerance radians
+// Dropped corners are measured again from the corners kept on either side, so a long gentle
+// curve keeps a corner wherever its turns add up to tolerance
+pub fn simplify_polygon(polygon: &[Point], min_edge: f32, tolerance: f32) -> Vec<Point> {
+    let mut points: Vec<Point> = distinct_points(polygon, min_edge)
+        .into_iter()
+        .map(|i| polygon[i])
+        .collect();
+    while points.len() > 3 {
+        let n = points.len();
+        let straight = (0..n).find(|&i| {
+            let (prev, cur, next) = (&points[(i + n - 1) % n], &points[i], &points[(i + 1) % n]);
+            turn_angle(prev, cur, next).abs() < tolerance
+        });
+        match straight {
+            Some(i) => {
+                points.remove(i);
+            }
+            None => break,
+        }
+    }
+    points
+}
+
+// Signed angle in radians the path from a through b turns by at b, positive to the left
+pub fn turn_angle(a: &Point, b: &Point, c: &Point) -> f32 {
+    let (ab, bc) = ((b.x - a.x, b.y - a.y), (c.x - b.x, c.y - b.y));
+    (ab.0 * bc.1 - ab.1 * bc.0).atan2(ab.0 * bc.0 + ab.1 * bc.1)
+}
+
+// Shortest distance from p to the edges of polygon, f32::MAX if it has none
+pub fn distance_to_edges(p: &Point, polygon: &[Point]) -> f32 {
+    (0..polygon.len())
+        .map(|i| {
+            let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+            let (_, _, dist_squared, _) = intersect_distance(a, b, p);
+            dist_squared.sqrt()
+        })
+        .fold(f32::MAX, f32::min)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -166,6 +261,17 @@ mod test {
         assert_eq!(intersect(&a, &c, &b, &d), true);
     }
 
+    #[test]
+    fn distinct_points_test() {
+        let a = Point::new(0f32, 0f32, 10f32);
+        let b = Point::new(0f32, 10f32, 10f32);
+        let c = Point::new(10f32, 10f32, 10f32);
+        // repeated point and closing point dropped, the indices kept refer to the input
+        let polygon = vec![a, b, b, c, a];
+        assert_eq!(distinct_points(&polygon, 0.5), vec![0, 1, 3]);
+        assert_eq!(distinct_points(&[a, a], 0.5), vec![0]);
+    }
+
     #[test]
     fn vertex_direction_test() {
         let a = Point::new(0f32, 0f32, 10f32);
@@ -180,4 +286,112 @@ mod test {
         assert_eq!(vertex_direction(&anticlockwise_flyzone), (false, false));
         assert_eq!(vertex_direction(&line_flyzone), (false, true));
     }
+
+    #[test]
+    fn closest_approach_test() {
+        let a = Point::new(0f32, 0f32, 10f32);
+        let b = Point::new(10f32, 0f32, 10f32);
+        // crossing paths meet halfway
+        let c = Point::new(5f32, -5f32, 10f32);
+        let d = Point::new(5f32, 5f32, 10f32);
+        assert!(closest_approach(&a, &b, &c, &d) < 0.001);
+        // same path a second apart stays 5 meters away
+        let c = Point::new(-5f32, 0f32, 10f32);
+        let d = Point::new(5f32, 0f32, 10f32);
+        assert!((closest_approach(&a, &b, &c, &d) - 5f32).abs() < 0.001);
+        // standing still beside the end
+        let c = Point::new(10f32, 3f32, 10f32);
+        assert!((closest_approach(&a, &b, &c, &c) - 3f32).abs() < 0.001);
+    }
+
+    #[test]
+    fn inside_test() {
+        // L shaped polygon missing its upper right quarter
+        let polygon = vec![
+            Point::new(0f32, 0f32, 0f32),
+            Point::new(10f32, 0f32, 0f32),
+            Point::new(10f32, 5f32, 0f32),
+            Point::new(5f32, 5f32, 0f32),
+            Point::new(5f32, 10f32, 0f32),
+            Point::new(0f32, 10f32, 0f32),
+        ];
+        assert!(inside(&Point::new(2f32, 2f32, 0f32), &polygon));
+        assert!(inside(&Point::new(8f32, 2f32, 0f32), &polygon));
+        assert!(inside(&Point::new(2f32, 8f32, 0f32), &polygon));
+        assert!(!inside(&Point::new(8f32, 8f32, 0f32), &polygon));
+        assert!(!inside(&Point::new(-1f32, 2f32, 0f32), &polygon));
+        assert!(!inside(&Point::new(2f32, 2f32, 0f32), &[]));
+    }
+
+    #[test]
+    fn distance_to_edges_test() {
+        let polygon = vec![
+            Point::new(0f32, 0f32, 0f32),
+            Point::new(10f32, 0f32, 0f32),
+            Point::new(10f32, 10f32, 0f32),
+            Point::new(0f32, 10f32, 0f32),
+        ];
+        assert_eq!(
+            distance_to_edges(&Point::new(3f32, 5f32, 0f32), &polygon),
+            3f32
+        );
+        assert_eq!(
+            distance_to_edges(&Point::new(5f32, 14f32, 0f32), &polygon),
+            4f32
+        );
+        assert_eq!(
+            distance_to_edges(&Point::new(13f32, 14f32, 0f32), &polygon),
+            5f32
+        );
+        assert_eq!(
+            distance_to_edges(&Point::new(0f32, 0f32, 0f32), &[]),
+            f32::MAX
+        );
+    }
+
+    #[test]
+    fn simplify_polygon_test() {
+        let a = Point::new(0f32, 0f32, 0f32);
+        let b = Point::new(10f32, 0f32, 0f32);
+        let c = Point::new(10f32, 10f32, 0f32);
+        let d = Point::new(0f32, 10f32, 0f32);
+        // repeated points, a closing point and corners along straight or nearly straight edges
+        let polygon = vec![
+            a,
+            Point::new(5f32, 0f32, 0f32),
+            b,
+            b,
+            Point::new(10f32, 5f32, 0f32),
+            Point::new(10.01, 7f32, 0f32),
+            c,
+            d,
+            a,
+        ];
+        let simplified = simplify_polygon(&polygon, 0.01, PI / 180f32);
+        assert_eq!(simplified.len(), 4);
+        for (p, expected) in simplified.iter().zip(&[a, b, c, d]) {
+            assert!(p.distance(expected) < 0.001);
+        }
+        // a gentle curve keeps a corner where its turns add up
+        let mut curve: Vec<Point> = (0..=90)
+            .map(|i| {
+                let angle = (i as f32 / 4f32).to_radians();
+                Point::new(angle.cos() * 100f32, angle.sin() * 100f32, 0f32)
+            })
+            .collect();
+        curve.push(a);
+        let simplified = simplify_polygon(&curve, 0.01, PI / 180f32);
+        let n = simplified.len();
+        assert!(n > 10 && n < curve.len());
+        for i in 0..n {
+            let (prev, cur, next) = (
+                &simplified[(i + n - 1) % n],
+                &simplified[i],
+                &simplified[(i + 1) % n],
+            );
+            assert!(turn_angle(prev, cur, next).abs() >= PI / 180f32);
+        }
+        assert!(turn_angle(&a, &b, &c) > 0f32);
+        assert!(turn_angle(&c, &b, &a) < 0f32);
+    }
 }
@@ -0,0 +1,414 @@
+// Any-angle planning on an occupancy grid with Theta*, an alternative to the tangent graph
+// Flyzones and obstacles are rasterized into square cells and the search moves between cell
+// centers, cutting straight to an earlier point of the path whenever it is in line of sight
+
+use super::*;
+use graph::util::inside;
+
+// Most cells a grid may have, about 100 MB for the search
+const MAX_CELLS: usize = 1 << 22;
+
+// Theta* on an occupancy grid, a planner to compare with the tangent graph
+pub struct GridPlanner {
+    cell_size: f32,     // In meters
+    grid: Option<Grid>, // Rasterized by prepare
+}
+
+impl GridPlanner {
+    // Plan on square cells cell_size meters wide
+    pub fn new(cell_size: f32) -> Result<Self, PathfinderError> {
+        if cell_size.is_nan() || cell_size <= 0f32 || cell_size.is_infinite() {
+            return Err(PathfinderError::InvalidParameter {
+                name: "cell_size",
+                value: cell_size,
+            });
+        }
+        Ok(GridPlanner {
+            cell_size: cell_size,
+            grid: None,
+        })
+    }
+}
+
+impl Planner for GridPlanner {
+    fn name(&self) -> &str {
+        "theta* grid"
+    }
+
+    fn prepare(&mut self, pathfinder: &Pathfinder) -> Result<(), PathfinderError> {
+        self.grid = Some(Grid::new(pathfinder, self.cell_size)?);
+        Ok(())
+    }
+
+    fn plan_leg(&self, pathfinder: &Pathfinder, leg: &Leg) -> Option<LinkedList<Location>> {
+        let (start, end) = pathfinder.leg_points(leg);
+        let path = self.grid.as_ref()?.find_path(pathfinder, &start, &end)?;
+        Some(pathfinder.to_locations(path))
+    }
+}
+
+struct Grid {
+    min: Point,         // Corner of the first cell
+    cell_size: f32,     // In meters
+    columns: usize,     // Cells along x
+    rows: usize,        // Cells along y
+    blocked: Vec<bool>, // By cell, one row after another
+}
+
+impl Grid {
+    // Rasterize the flyzones and obstacles of pathfinder
+    // Cells reaching outside the flyzones, into an exclusion or into an obstacle are blocked,
+    // obstacles are avoided at any altitude and moving obstacles where they are now
+    // Cells too small for the flyzones to fit in MAX_CELLS are an invalid cell_size
+    pub fn new(pathfinder: &Pathfinder, cell_size: f32) -> Result<Self, PathfinderError> {
+        let flyzones = &pathfinder.boundaries;
+        let exclusions = pathfinder.exclusion_polygons();
+        let (mut obstacles, mut prisms) = (Vec::new(), Vec::new());
+        for obs in &pathfinder.obstacles {
+            let buffer = pathfinder.obstacle_buffer(obs);
+            match obs.shape {
+                Shape::Cylinder | Shape::Sphere => {
+                    let center = Point::from_location(&obs.location, &pathfinder.origin);
+                    obstacles.push((center, obs.radius + buffer));
+                }
+                Shape::Prism(ref footprint) => {
+                    let polygon: Vec<Point> = footprint
+                        .iter()
+                        .map(|location| Point::from_location(location, &pathfinder.origin))
+                        .collect();
+                    prisms.push((polygon, buffer));
+                }
+            }
+        }
+        let (mut min, mut max) = (
+            Point::new(f32::MAX, f32::MAX, 0f32),
+            Point::new(f32::MIN, f32::MIN, 0f32),
+        );
+        for point in flyzones.iter().flatten() {
+            min = Point::new(min.x.min(point.x), min.y.min(point.y), 0f32);
+            max = Point::new(max.x.max(point.x), max.y.max(point.y), 0f32);
+        }
+        let columns = ((max.x - min.x) / cell_size).ceil().max(1f32);
+        let rows = ((max.y - min.y) / cell_size).ceil().max(1f32);
+        if columns * rows > MAX_CELLS as f32 {
+            return Err(PathfinderError::InvalidParameter {
+                name: "cell_size",
+                value: cell_size,
+            });
+        }
+        let (columns, rows) = (columns as usize, rows as usize);
+        let mut grid = Grid {
+            min: min,
+            cell_size: cell_size,
+            columns: columns,
+            rows: rows,
+            blocked: Vec::with_capacity(columns * rows),
+        };
+        // a cell is clear when its center is further than half its diagonal from any edge
+        let half = cell_size * 2f32.sqrt() / 2f32;
+        let clear_of_edges = |polygon: &Vec<Point>, center: &Point, margin: f32| {
+            (0..polygon.len()).all(|i| {
+                let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+                let (_, _, dist_squared, _) = intersect_distance(a, b, center);
+                dist_squared > margin.powi(2)
+            })
+        };
+        for cell in 0..columns * rows {
+            let center = grid.center(cell);
+            let in_flyzone = flyzones
+                .iter()
+                .any(|flyzone| inside(&center, flyzone) && clear_of_edges(flyzone, &center, half));
+            let in_exclusion = exclusions
+                .iter()
+                .any(|polygon| inside(&center, polygon) || !clear_of_edges(polygon, &center, half));
+            let in_obstacle = obstacles
+                .iter()
+                .any(|&(ref origin, radius)| center.distance(origin) < radius + half)
+                || prisms.iter().any(|&(ref polygon, buffer)| {
+                    inside(&center, polygon) || !clear_of_edges(polygon, &center, buffer + half)
+                });
+            grid.blocked
+                .push(!in_flyzone || in_exclusion || in_obstacle);
+        }
+        Ok(grid)
+    }
+
+    // Find a path from start to end, returned as the points where it turns followed by end
+    // Altitude changes steadily from start to end along the path
+    // Returns none if start or end is in a blocked cell, there is no path or time ran out
+    pub fn find_path(
+        &self,
+        pathfinder: &Pathfinder,
+        start: &Point,
+        end: &Point,
+    ) -> Option<LinkedList<Point>> {
+        let (first, last) = (self.free_cell(start)?, self.free_cell(end)?);
+        if first == last {
+            let mut path = LinkedList::new();
+            path.push_back(*end);
+            return Some(path);
+        }
+        // the first and last cells stand for the exact start and end
+        let position = |cell: usize| {
+            if cell == first {
+                *start
+            } else if cell == last {
+                *end
+            } else {
+                self.center(cell)
+            }
+        };
+        let size = self.columns * self.rows;
+        let mut g_cost = vec![f32::MAX; size];
+        let mut parent: Vec<Option<usize>> = vec![None; size];
+        let mut closed = vec![false; size];
+        let mut open_list = BinaryHeap::new();
+        g_cost[first] = 0f32;
+        open_list.push((Reverse(OrderedFloat(start.distance(end))), first));
+        while let Some((_, cur)) = open_list.pop() {
+            if pathfinder.timed_out() {
+                return None;
+            }
+            if closed[cur] {
+                continue;
+            }
+            closed[cur] = true;
+            let location = position(cur).to_location(&pathfinder.origin);
+            pathfinder.observer.node_expanded(&location, g_cost[cur]);
+            if cur == last {
+                return Some(self.generate_path(&parent, last, &position));
+            }
+            for next in self.neighbors(cur) {
+                if closed[next] {
+                    continue;
+                }
+                let p = position(next);
+                // go straight from the parent of cur if nothing is in the way
+                let from = match parent[cur] {
+                    Some(prev) if self.line_of_sight(&position(prev), &p) => prev,
+                    _ if self.line_of_sight(&position(cur), &p) => cur,
+                    _ => continue,
+                };
+                let new_g_cost = g_cost[from] + position(from).distance(&p);
+                if new_g_cost >= g_cost[next] {
+                    continue;
+                }
+                g_cost[next] = new_g_cost;
+                parent[next] = Some(from);
+                let new_f_cost = new_g_cost + p.distance(end);
+                open_list.push((Reverse(OrderedFloat(new_f_cost)), next));
+            }
+        }
+        None
+    }
+
+    // Points of the path to last, excluding the start, with altitudes from start to end
+    fn generate_path<F: Fn(usize) -> Point>(
+        &self,
+        parent: &[Option<usize>],
+        last: usize,
+        position: &F,
+    ) -> LinkedList<Point> {
+        let mut points = vec![position(last)];
+        let mut cur = last;
+        while let Some(prev) = parent[cur] {
+            points.push(position(prev));
+            cur = prev;
+        }
+        points.reverse();
+        let (start, end) = (points[0], points[points.len() - 1]);
+        let total: f32 = points.windows(2).map(|w| w[0].distance(&w[1])).sum();
+        let mut path = LinkedList::new();
+        let mut length = 0f32;
+        for w in points.windows(2) {
+            length += w[0].distance(&w[1]);
+            let z = if total > 0f32 {
+                start.z + (end.z - start.z) * length / total
+            } else {
+                end.z
+            };
+            path.push_back(Point::new(w[1].x, w[1].y, z));
+        }
+        path
+    }
+
+    // true if the segment from a to b only crosses free cells
+    // Passing exactly through a corner also requires both cells beside it to be free
+    fn line_of_sight(&self, a: &Point, b: &Point) -> bool {
+        let (ax, ay) = (
+            (a.x - self.min.x) / self.cell_size,
+            (a.y - self.min.y) / self.cell_size,
+        );
+        let (bx, by) = (
+            (b.x - self.min.x) / self.cell_size,
+            (b.y - self.min.y) / self.cell_size,
+        );
+        let (mut x, mut y) = (ax.floor() as isize, ay.floor() as isize);
+        let (end_x, end_y) = (bx.floor() as isize, by.floor() as isize);
+        let (dx, dy) = (bx - ax, by - ay);
+        let (step_x, step_y) = (dx.signum() as isize, dy.signum() as isize);
+        // fraction of the segment until the next vertical and horizontal cell border, and
+        // between two borders
+        let first_border = |from: f32, cell: isize, d: f32| {
+            if d > 0f32 {
+                (cell as f32 + 1f32 - from) / d
+            } else if d < 0f32 {
+                (from - cell as f32) / -d
+            } else {
+                f32::INFINITY
+            }
+        };
+        let (mut t_x, mut t_y) = (first_border(ax, x, dx), first_border(ay, y, dy));
+        let (delta_x, delta_y) = (1f32 / dx.abs(), 1f32 / dy.abs());
+        let steps = (end_x - x).abs() + (end_y - y).abs();
+        for _ in 0..=steps {
+            if self.blocked_at(x, y) {
+                return false;
+            }
+            if (x, y) == (end_x, end_y) {
+                return true;
+            }
+            if t_x < t_y {
+                x += step_x;
+                t_x += delta_x;
+            } else if t_y < t_x {
+                y += step_y;
+                t_y += delta_y;
+            } else {
+                if self.blocked_at(x + step_x, y) || self.blocked_at(x, y + step_y) {
+                    return false;
+                }
+                x += step_x;
+                y += step_y;
+                t_x += delta_x;
+                t_y += delta_y;
+            }
+        }
+        !self.blocked_at(end_x, end_y)
+    }
+
+    // Free cells around cell, diagonals included
+    fn neighbors(&self, cell: usize) -> Vec<usize> {
+        let (x, y) = (
+            (cell % self.columns) as isize,
+            (cell / self.columns) as isize,
+        );
+        let mut neighbors = Vec::new();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) != (0, 0) && !self.blocked_at(x + dx, y + dy) {
+                    neighbors.push((y + dy) as usize * self.columns + (x + dx) as usize);
+                }
+            }
+        }
+        neighbors
+    }
+
+    // Cell containing p if it is free
+    fn free_cell(&self, p: &Point) -> Option<usize> {
+        let x = ((p.x - self.min.x) / self.cell_size).floor() as isize;
+        let y = ((p.y - self.min.y) / self.cell_size).floor() as isize;
+        if self.blocked_at(x, y) {
+            None
+        } else {
+            Some(y as usize * self.columns + x as usize)
+        }
+    }
+
+    // Cells outside the grid are blocked
+    fn blocked_at(&self, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.columns || y as usize >= self.rows {
+            return true;
+        }
+        self.blocked[y as usize * self.columns + x as usize]
+    }
+
+    fn center(&self, cell: usize) -> Point {
+        let (x, y) = (cell % self.columns, cell / self.columns);
+        Point::new(
+            self.min.x + (x as f32 + 0.5) * self.cell_size,
+            self.min.y + (y as f32 + 0.5) * self.cell_size,
+            0f32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn grid_test() {
+        let create = |builder: PathfinderBuilder| {
+            builder
+                .create(square_flyzone(), vec![center_obstacle(50f32, 200f32)])
+                .unwrap()
+        };
+        // points of the path from the plane at x, climbing to the waypoint
+        let plan = |pathfinder: &mut Pathfinder, x: f32| {
+            let (_, waypoints) = crossing(50f32, 80f32);
+            let plane = Plane::new(at(x, 200f32, 50f32));
+            let mut points = vec![project(pathfinder, &plane.location)];
+            let origin = pathfinder.origin;
+            let path = pathfinder.try_get_adjust_path(plane, waypoints)?;
+            points.extend(
+                path.iter()
+                    .map(|wp| Point::from_location(&wp.location, &origin)),
+            );
+            Ok(points)
+        };
+        assert!(GridPlanner::new(0f32).is_err());
+        let mut grid = create(PathfinderBuilder::new().planner(GridPlanner::new(2f32).unwrap()));
+        assert_eq!(grid.get_planner().name(), "theta* grid");
+        let points: Vec<Point> = plan(&mut grid, 50f32).unwrap();
+        let center = project(&grid, &at(200f32, 200f32, 200f32));
+        for i in 1..points.len() {
+            let (_, _, dist, _) = intersect_distance(&points[i - 1], &points[i], &center);
+            assert!(dist.sqrt() > 50f32);
+            // climbs steadily to the waypoint
+            assert!(points[i].z >= points[i - 1].z);
+        }
+        let length =
+            |points: &[Point]| -> f32 { points.windows(2).map(|w| w[0].distance(&w[1])).sum() };
+        // close to the tangent graph, which follows the obstacle edge exactly
+        let graph_points = plan(&mut create(PathfinderBuilder::new()), 50f32).unwrap();
+        assert!(length(&points) < length(&graph_points) * 1.04);
+
+        // the plane inside the obstacle has no cell to leave from
+        assert_eq!(
+            plan(&mut grid, 190f32).err(),
+            Some(PathfinderError::UnreachableLeg { from: None, to: 0 })
+        );
+        grid.remove_obstacle(grid.get_obstacle_ids()[0]).unwrap();
+        assert_eq!(plan(&mut grid, 190f32).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn max_cells_test() {
+        let flyzone = vec![
+            at(0f32, 0f32, 10f32),
+            at(0f32, 4000f32, 10f32),
+            at(4000f32, 4000f32, 10f32),
+            at(4000f32, 0f32, 10f32),
+        ];
+        let (plane, waypoints) = crossing(50f32, 50f32);
+        let plan = |cell_size: f32| {
+            PathfinderBuilder::new()
+                .planner(GridPlanner::new(cell_size).unwrap())
+                .create(vec![flyzone.clone()], Vec::new())
+                .unwrap()
+                .try_get_adjust_path(plane, waypoints.clone())
+                .map(|path| path.len())
+        };
+        // 4 km across in 1 m cells is 16 million cells
+        assert_eq!(
+            plan(1f32),
+            Err(PathfinderError::InvalidParameter {
+                name: "cell_size",
+                value: 1f32,
+            })
+        );
+        assert_eq!(plan(5f32), Ok(1));
+    }
+}
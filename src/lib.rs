@@ -2,49 +2,100 @@
 #![allow(unused_variables)]
 
 extern crate ordered_float;
+extern crate toml;
 
-use std::cell::RefCell;
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::collections::LinkedList;
 use std::f32::consts::PI;
-use std::rc::Rc;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use std::time::{Duration, SystemTime};
 
+use ordered_float::OrderedFloat;
+
+mod builder;
+mod config;
+mod dubins;
+mod error;
+#[cfg(test)]
+mod fixture;
 mod graph;
+mod grid;
 pub mod obj;
+mod observer;
+mod path;
+mod planner;
+mod query;
+mod repair;
+mod replan;
+
+pub use builder::PathfinderBuilder;
+pub use config::PathfinderConfig;
+pub use error::PathfinderError;
+use graph::util::{distinct_points, intersect, intersect_distance};
+use graph::{Arena, Graph, Node, NodeId, Overlay, PathValidity, Point, VertexId};
+pub use grid::GridPlanner;
+pub use obj::{Flyzone, Location, Obstacle, Plane, Shape, Waypoint};
+pub use observer::{LogObserver, PlannerObserver, Rejection, SilentObserver};
+pub use path::{Direction, Path, PathSegment};
+pub use planner::{Leg, Planner, TangentGraph};
+pub use repair::WaypointProblem;
+use replan::Replanner;
 
-use graph::util::intersect;
-use graph::{Connection, Node, Point, Vertex};
-pub use obj::{Location, Obstacle, Plane, Waypoint};
+// Identifies an obstacle of a pathfinder, unchanged as other obstacles are added or removed
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ObstacleId(u32);
 
 const EQUATORIAL_RADIUS: f64 = 63781370.0;
 const POLAR_RADIUS: f64 = 6356752.0;
 const RADIUS: f64 = 6371000.0;
 const MIN_BUFFER: f32 = 5f32;
 const TURNING_RADIUS: f32 = 5f32; // In meters
-const MAX_ANGLE: f32 = PI / 6f32;
+const MIN_EDGE: f32 = 0.01; // In meters, shorter flyzone edges are merged into their neighbours
+const STRAIGHT_ANGLE: f32 = PI / 180f32; // Flyzone corners turning less are treated as straight
+const CORNER_STEPS: usize = 20; // Places tried for a flyzone corner node, up to twice as deep
 const MAX_ANGLE_ASCENT: f32 = PI / 3f32;
 const MAX_ANGLE_DESCENT: f32 = -PI / 3f32;
-const START_VERTEX_INDEX: i32 = -1;
-const END_VERTEX_INDEX: i32 = -2;
-const HEADER_VERTEX_INDEX: i32 = -3;
+const SPEED: f32 = 15f32; // In meters per second
 
 #[allow(non_snake_case)]
 pub struct Pathfinder {
     // exposed API
     buffer: f32,                // In meters
     max_process_time: Duration, // In seconds
-    flyzones: Vec<Vec<Location>>,
+    flyzones: Vec<Flyzone>,
+    exclusions: Vec<Vec<Location>>, // Areas the plane must stay out of, besides flyzone holes
     obstacles: Vec<Obstacle>,
+    obstacle_ids: Vec<ObstacleId>, // Parallel to obstacles
+    config: PathfinderConfig,
+    observer: Box<dyn PlannerObserver>,
+    planner: Box<dyn Planner>,
+    // vehicle limits
+    turning_radius: f32,    // In meters
+    max_angle_ascent: f32,  // In radians
+    max_angle_descent: f32, // In radians
+    min_buffer: f32,        // In meters
+    speed: f32,             // In meters per second
     // private
     initialized: bool,
     start_time: SystemTime,
-    current_wp: Waypoint,
+    complete: bool,         // false if the last path ran out of time
+    parallel: bool,         // plan legs on multiple threads
+    incremental: bool,      // keep the search of each leg between calls
+    dubins: bool,           // join the points of the path with Dubins paths
+    repair_waypoints: bool, // move waypoints that cannot be reached instead of failing
+    prepared: bool,         // planner is ready for the current flyzones and obstacles
     wp_list: LinkedList<Waypoint>,
     origin: Location, // Reference point defining each node
-    nodes: Vec<Rc<RefCell<Node>>>,
-    num_vertices: i32,
+    graph: Graph,
+    boundaries: Vec<Vec<Point>>, // Parallel to flyzones, projected with the graph
+    exclusion_points: Vec<Vec<Point>>, // Holes then exclusions, projected with the graph
+    obstacle_nodes: Vec<Vec<NodeId>>, // Parallel to obstacles, empty if moving
+    next_obstacle_id: u32,
+    replanners: HashMap<u32, Replanner>, // Kept searches by waypoint index
 }
 
 impl Pathfinder {
@@ -54,296 +105,1119 @@ impl Pathfinder {
             buffer: MIN_BUFFER,
             max_process_time: Duration::from_secs(10u64),
             flyzones: Vec::new(),
+            exclusions: Vec::new(),
             obstacles: Vec::new(),
+            obstacle_ids: Vec::new(),
+            config: PathfinderConfig::default(),
+            observer: Box::new(SilentObserver),
+            planner: Box::new(TangentGraph),
+            // vehicle limits
+            turning_radius: TURNING_RADIUS,
+            max_angle_ascent: MAX_ANGLE_ASCENT,
+            max_angle_descent: MAX_ANGLE_DESCENT,
+            min_buffer: MIN_BUFFER,
+            speed: SPEED,
             // private
             initialized: false,
             start_time: SystemTime::now(),
-            current_wp: Waypoint::from_degrees(0u32, 0f64, 0f64, 0f32, 1f32),
+            complete: true,
+            parallel: false,
+            incremental: false,
+            dubins: false,
+            repair_waypoints: false,
+            prepared: false,
             wp_list: LinkedList::new(),
             origin: Location::from_degrees(0f64, 0f64, 0f32),
-            nodes: Vec::new(),
-            num_vertices: 0i32,
+            graph: Graph::new(),
+            boundaries: Vec::new(),
+            exclusion_points: Vec::new(),
+            obstacle_nodes: Vec::new(),
+            next_obstacle_id: 0,
+            replanners: HashMap::new(),
         }
     }
 
+    // Pathfinder weighting paths with config, e.g. PathfinderConfig::load()?
+    pub fn from_config(config: PathfinderConfig) -> Pathfinder {
+        let mut pathfinder = Pathfinder::new();
+        pathfinder.config = config;
+        pathfinder
+    }
+
     // Helper function to return an initialized pathfinder
-    pub fn create(
+    pub fn create<F: Into<Flyzone>>(
         buffer_size: f32,
-        flyzones: Vec<Vec<Location>>,
+        flyzones: Vec<F>,
         obstacles: Vec<Obstacle>,
     ) -> Self {
+        match Self::try_create(buffer_size, flyzones, obstacles) {
+            Ok(pathfinder) => pathfinder,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_create<F: Into<Flyzone>>(
+        buffer_size: f32,
+        flyzones: Vec<F>,
+        obstacles: Vec<Obstacle>,
+    ) -> Result<Self, PathfinderError> {
         let mut pathfinder = Pathfinder::new();
-        pathfinder.init(buffer_size, flyzones, obstacles);
-        pathfinder
-    } //          let p1 = a.to_point(*j + theta0);
-      //          println!("{} {:?}", j, &p1);
-      //          let p2 = b.to_point(*i + theta0);
-      //          println!("{} {:?}", i, &
+        pathfinder.try_init(buffer_size, flyzones, obstacles)?;
+        Ok(pathfinder)
+    }
 
-    pub fn init(
+    pub fn init<F: Into<Flyzone>>(
         &mut self,
         buffer_size: f32,
-        flyzones: Vec<Vec<Location>>,
+        flyzones: Vec<F>,
         obstacles: Vec<Obstacle>,
     ) {
-        assert!(flyzones.len() >= 1);
-        for flyzone in &flyzones {
-            assert!(flyzone.len() >= 3);
+        if let Err(e) = self.try_init(buffer_size, flyzones, obstacles) {
+            panic!("{}", e);
         }
-        self.buffer = buffer_size.max(MIN_BUFFER);
+    }
+
+    pub fn try_init<F: Into<Flyzone>>(
+        &mut self,
+        buffer_size: f32,
+        flyzones: Vec<F>,
+        obstacles: Vec<Obstacle>,
+    ) -> Result<(), PathfinderError> {
+        let flyzones: Vec<Flyzone> = flyzones.into_iter().map(Into::into).collect();
+        self.validate_flyzones(&flyzones)?;
+        obstacles.iter().try_for_each(validate_obstacle)?;
+        self.buffer = buffer_size.max(self.min_buffer);
         self.flyzones = flyzones;
-        for i in 0..self.flyzones.len() {
-            if self.invalid_flyzone(i) {
-                panic!();
-            }
-        }
         self.obstacles = obstacles;
+        self.assign_obstacle_ids();
         self.build_graph();
         self.initialized = true;
+        Ok(())
+    }
+
+    // check that there is at least one flyzone and that every flyzone is a simple polygon with
+    // its floor no higher than its ceiling
+    fn validate_flyzones(&self, flyzones: &[Flyzone]) -> Result<(), PathfinderError> {
+        if flyzones.is_empty() {
+            return Err(PathfinderError::NoFlyzone);
+        }
+        for (i, flyzone) in flyzones.iter().enumerate() {
+            let boundary = &flyzone.boundary;
+            if boundary.len() < 3 {
+                return Err(PathfinderError::TooFewPoints {
+                    flyzone: i,
+                    points: boundary.len(),
+                });
+            }
+            if let Some(edges) = self.flyzone_intersection(boundary) {
+                return Err(PathfinderError::SelfIntersectingFlyzone { flyzone: i, edges });
+            }
+            for (j, hole) in flyzone.holes.iter().enumerate() {
+                if hole.len() < 3 || self.flyzone_intersection(hole).is_some() {
+                    return Err(PathfinderError::InvalidHole {
+                        flyzone: i,
+                        hole: j,
+                    });
+                }
+            }
+            if flyzone.min_alt.is_nan() {
+                return Err(PathfinderError::InvalidParameter {
+                    name: "min_alt",
+                    value: flyzone.min_alt,
+                });
+            }
+            if flyzone.max_alt.is_nan() || flyzone.max_alt < flyzone.min_alt {
+                return Err(PathfinderError::InvalidParameter {
+                    name: "max_alt",
+                    value: flyzone.max_alt,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // check that every exclusion polygon is a simple polygon
+    fn validate_exclusions(&self, exclusions: &[Vec<Location>]) -> Result<(), PathfinderError> {
+        for (i, exclusion) in exclusions.iter().enumerate() {
+            if exclusion.len() < 3 || self.flyzone_intersection(exclusion).is_some() {
+                return Err(PathfinderError::InvalidExclusion(i));
+            }
+        }
+        Ok(())
     }
 
     // determine if flyzone intersects itself (correct order)
     // inputs (self,flyzones indices), outputs true if invalid
     fn invalid_flyzone(&mut self, iter: usize) -> (bool) {
-        let flyzone = &self.flyzones[iter];
-        let mut vertices = Vec::new();
-        for loc in 0..flyzone.len() {
-            let i = flyzone[loc];
-            let point = Point::from_location(&i, &self.origin);
-            vertices.push(point);
-        }
+        self.flyzone_intersection(&self.flyzones[iter].boundary)
+            .is_some()
+    }
+
+    // find the first pair of intersecting edges in a flyzone
+    // edges are identified by the index of their first point
+    // repeated points, a closing point included, are skipped instead of counted as touching
+    fn flyzone_intersection(&self, flyzone: &[Location]) -> Option<(usize, usize)> {
+        let points: Vec<Point> = flyzone
+            .iter()
+            .map(|location| Point::from_location(location, &self.origin))
+            .collect();
+        let vertices: Vec<(usize, Point)> = distinct_points(&points, MIN_EDGE)
+            .into_iter()
+            .map(|i| (i, points[i]))
+            .collect();
         let n = vertices.len();
+        if n < 3 {
+            return None;
+        }
         // compares any side of flyzone, ab, with any non-adjacent side, cd
         for ab in 0..n - 2 {
-            let a = vertices[ab];
-            let b = vertices[ab + 1];
+            let a = vertices[ab].1;
+            let b = vertices[ab + 1].1;
             for i in 2..n - 1 {
                 let cd = ab + i;
-                let c = vertices[cd];
-                let d = vertices[(cd + 1) % n];
+                let c = vertices[cd].1;
+                let d = vertices[(cd + 1) % n].1;
                 if intersect(&a, &b, &c, &d) {
-                    return true;
+                    return Some((vertices[ab].0, vertices[cd].0));
                 }
                 if cd + 1 == n {
                     break;
                 }
             }
         }
-        return false;
+        None
     }
 
+    // Legacy interface: returns the legs solved before any unreachable leg
     pub fn get_adjust_path(
         &mut self,
         plane: Plane,
-        mut wp_list: LinkedList<Waypoint>,
+        wp_list: LinkedList<Waypoint>,
     ) -> &LinkedList<Waypoint> {
-        assert!(self.initialized);
+        if let Err(PathfinderError::Uninitialized) = self.try_get_adjust_path(plane, wp_list) {
+            panic!("{}", PathfinderError::Uninitialized);
+        }
+        &self.wp_list
+    }
+
+    pub fn try_get_adjust_path(
+        &mut self,
+        plane: Plane,
+        wp_list: LinkedList<Waypoint>,
+    ) -> Result<&LinkedList<Waypoint>, PathfinderError> {
+        if !self.initialized {
+            return Err(PathfinderError::Uninitialized);
+        }
         self.start_time = SystemTime::now();
+        self.complete = true;
         self.wp_list = LinkedList::new();
-        let mut current_loc: Location;
-        let mut next_loc: Location;
+        let wp_list = self.prepare_waypoints(wp_list)?;
+        if !self.prepared {
+            let mut planner = mem::replace(&mut self.planner, Box::new(TangentGraph));
+            let prepared = planner.prepare(self);
+            self.planner = planner;
+            prepared?;
+            self.prepared = true;
+        }
+        let waypoints: Vec<Waypoint> = wp_list.into_iter().collect();
+        // the first leg leaves from the plane in the direction it is flying
+        let heading = plane_heading(&plane);
+        // the kept searches cannot account for obstacles moving
+        let moving = self.obstacles.iter().any(|o| o.is_moving());
+        let legs = if self.incremental && !moving {
+            self.replan_legs(plane.location, heading, &waypoints)
+        } else if self.parallel {
+            self.plan_legs_parallel(plane.location, heading, &waypoints)
+        } else {
+            self.plan_legs(plane.location, heading, &waypoints)
+        };
+        let legs = if self.dubins {
+            self.fly_dubins(plane.location, heading, legs, &waypoints)?
+        } else {
+            legs
+        };
+        let legs: Vec<Option<LinkedList<Location>>> = legs
+            .into_iter()
+            .enumerate()
+            .map(|(i, leg)| {
+                let start = if i == 0 {
+                    plane.location
+                } else {
+                    waypoints[i - 1].location
+                };
+                leg.map(|leg| self.assign_altitudes(&start, leg))
+            })
+            .collect();
 
-        // First destination is first waypoint
-        match wp_list.pop_front() {
-            Some(wp) => self.current_wp = wp,
-            None => return &self.wp_list,
+        for (i, leg) in legs.into_iter().enumerate() {
+            let from = if i == 0 {
+                None
+            } else {
+                Some(waypoints[i - 1].index)
+            };
+            if let Some(leg) = leg {
+                let mut path: LinkedList<Waypoint> = leg
+                    .into_iter()
+                    .map(|location| waypoints[i].extend(location, location.alt()))
+                    .collect();
+                self.observer.leg_solved(from, waypoints[i].index, &path);
+                self.wp_list.append(&mut path);
+            } else if self.timed_out() {
+                // return the legs solved so far as the best partial result
+                self.complete = false;
+                if self.wp_list.is_empty() {
+                    return Err(PathfinderError::Timeout);
+                }
+                return Ok(&self.wp_list);
+            } else {
+                return Err(PathfinderError::UnreachableLeg {
+                    from,
+                    to: waypoints[i].index,
+                });
+            }
         }
 
-        current_loc = plane.location;
-        next_loc = self.current_wp.location;
-        self.adjust_path(current_loc, next_loc);
-        // self.wp_list.push_back(self.current_wp.clone()); // Push original waypoint
+        Ok(&self.wp_list)
+    }
 
-        loop {
-            current_loc = self.current_wp.location;
-            match wp_list.pop_front() {
-                Some(wp) => self.current_wp = wp,
+    // Solve legs one after another, each leg leaving in the direction the previous one arrived
+    // Stops at the first leg without a path
+    fn plan_legs(
+        &self,
+        start: Location,
+        heading: Option<f32>,
+        waypoints: &[Waypoint],
+    ) -> Vec<Option<LinkedList<Location>>> {
+        let mut legs = Vec::new();
+        let mut leg = first_leg(start, heading);
+        for waypoint in waypoints {
+            leg.to = waypoint.index;
+            leg.end = waypoint.location;
+            let path = self.planner.plan_leg(self, &leg);
+            let next = path.as_ref().map(|path| self.next_leg(&leg, path));
+            legs.push(path);
+            match next {
+                Some(next) => leg = next,
                 None => break,
             }
-            next_loc = self.current_wp.location;
+        }
+        legs
+    }
 
-            if let Some(mut wp_list) = self.adjust_path(current_loc, next_loc) {
-                self.wp_list.append(&mut wp_list);
-            } else {
-                println!("no path");
-                break;
+    // Solve legs one after another like plan_legs, letting the planner repair what it kept
+    // from the previous call for each leg instead of starting over
+    fn replan_legs(
+        &mut self,
+        start: Location,
+        heading: Option<f32>,
+        waypoints: &[Waypoint],
+    ) -> Vec<Option<LinkedList<Location>>> {
+        let mut planner = mem::replace(&mut self.planner, Box::new(TangentGraph));
+        let mut legs = Vec::new();
+        let mut leg = first_leg(start, heading);
+        for waypoint in waypoints {
+            leg.to = waypoint.index;
+            leg.end = waypoint.location;
+            let path = planner.replan(self, &leg);
+            let next = path.as_ref().map(|path| self.next_leg(&leg, path));
+            legs.push(path);
+            match next {
+                Some(next) => leg = next,
+                None => break,
+            }
+        }
+        self.planner = planner;
+        // searches kept for legs no longer in the mission are dropped, those of legs not
+        // reached this time are kept
+        let dropped: Vec<u32> = self
+            .replanners
+            .keys()
+            .filter(|&&index| waypoints.iter().all(|wp| wp.index != index))
+            .cloned()
+            .collect();
+        for index in dropped {
+            if let Some(replanner) = self.replanners.remove(&index) {
+                self.graph.remove_node(replanner.goal);
             }
-            // self.wp_list.push_back(self.current_wp.clone()); // Push original waypoint
         }
+        self.trim_changes();
+        legs
+    }
 
-        &self.wp_list
+    // Drop the graph changes every kept search has already taken into account
+    fn trim_changes(&mut self) {
+        let seen = self
+            .replanners
+            .values()
+            .map(Replanner::changes_seen)
+            .min()
+            .unwrap_or(self.graph.changes.len());
+        self.graph.changes.drain(..seen);
+        for replanner in self.replanners.values_mut() {
+            replanner.forget_changes(seen);
+        }
     }
 
-    // Find best path using the a* algorithm
-    // Return path if found and none if any error occured or no path found
-    fn adjust_path(&mut self, start: Location, end: Location) -> Option<LinkedList<Waypoint>> {
-        let mut num_vertices = self.num_vertices;
-        let mut open_list: BinaryHeap<Rc<RefCell<Vertex>>> = BinaryHeap::new();
-        let mut open_set: HashSet<i32> = HashSet::new();
-        let mut closed_set: HashSet<i32> = HashSet::new();
-        let mut vertices_to_remove: LinkedList<Rc<RefCell<Vertex>>> = LinkedList::new();
-        let start_node = Rc::new(RefCell::new(Node::from_location(&start, &self.origin)));
-        let end_node = Rc::new(RefCell::new(Node::from_location(&end, &self.origin)));
-        let start_vertex = Vertex::new(start_node.clone(), &mut START_VERTEX_INDEX, 0f32, None);
-
-        //Prepare graph for A*
-        for i in 0..self.nodes.len() {
-            let temp_node = &self.nodes[i];
-            let (temp_paths, _) = self.find_path(&start_node.borrow(), &temp_node.borrow());
-            for (a, b, dist, thresh) in temp_paths.iter() {
-                let vertex = Rc::new(RefCell::new(Vertex::new(
-                    temp_node.clone(),
-                    &mut num_vertices,
-                    *b,
-                    None,
-                )));
-                temp_node.borrow_mut().insert_vertex(vertex.clone());
-                open_list.push(vertex.clone());
-                vertices_to_remove.push_back(vertex.clone());
-                open_set.insert(vertex.borrow().index);
+    // Plan leg with the D* Lite search kept for its waypoint, starting a new search if the
+    // waypoint moved
+    // The leg is searched from scratch when it has no path without flying over an obstacle
+    fn replan_leg(&mut self, leg: &Leg) -> Option<LinkedList<Point>> {
+        let (start, end) = self.leg_points(leg);
+        let mut replanner = match self.replanners.remove(&leg.to) {
+            Some(replanner) if replanner.leads_to(&end) => replanner,
+            Some(replanner) => {
+                self.graph.remove_node(replanner.goal);
+                Replanner::new(self, start, end)
             }
+            None => Replanner::new(self, start, end),
+        };
+        let path = replanner.plan(self, start, leg.heading);
+        self.replanners.insert(leg.to, replanner);
+        if path.is_none() && !self.timed_out() {
+            return self.adjust_path(start, end, leg.heading, leg.depart);
+        }
+        path
+    }
 
-            let (temp_paths, _) = self.find_path(&temp_node.borrow(), &end_node.borrow());
-            for (a, b, dist, thresh) in temp_paths.iter() {
-                let end_vertex = Rc::new(RefCell::new(Vertex::new(
-                    end_node.clone(),
-                    &mut END_VERTEX_INDEX,
-                    *b,
-                    None,
-                )));
-                let connection = Connection::new(end_vertex.clone(), *dist, *thresh);
-                let vertex = Rc::new(RefCell::new(Vertex::new(
-                    temp_node.clone(),
-                    &mut num_vertices,
-                    *a,
-                    Some(connection),
-                )));
-                vertices_to_remove.push_back(vertex.clone());
-                temp_node.borrow_mut().insert_vertex(vertex.clone());
+    // Solve legs at the same time on one thread per core, results are in mission order
+    // Legs after the first leave in any direction since the arrival heading is not known yet,
+    // and at the time flying straight through the previous waypoints would take
+    fn plan_legs_parallel(
+        &self,
+        start: Location,
+        heading: Option<f32>,
+        waypoints: &[Waypoint],
+    ) -> Vec<Option<LinkedList<Location>>> {
+        let mut planned = Vec::new();
+        let mut leg = first_leg(start, heading);
+        for waypoint in waypoints {
+            leg.to = waypoint.index;
+            leg.end = waypoint.location;
+            planned.push(leg);
+            let (a, b) = self.leg_points(&leg);
+            leg = Leg {
+                from: Some(waypoint.index),
+                start: waypoint.location,
+                heading: None,
+                depart: leg.depart + a.distance(&b) / self.speed,
+                ..leg
+            };
+        }
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(planned.len());
+        let next_leg = AtomicUsize::new(0);
+        let mut legs = vec![None; planned.len()];
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut solved = Vec::new();
+                        loop {
+                            let i = next_leg.fetch_add(1, Ordering::Relaxed);
+                            if i >= planned.len() {
+                                return solved;
+                            }
+                            solved.push((i, self.planner.plan_leg(self, &planned[i])));
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                for (i, path) in handle.join().unwrap() {
+                    legs[i] = path;
+                }
             }
+        });
+        legs
+    }
+
+    // Leg leaving the end of leg in the direction its path arrives, at the time the plane gets
+    // there; the caller sets the waypoint it goes to
+    fn next_leg(&self, leg: &Leg, path: &LinkedList<Location>) -> Leg {
+        let (start, _) = self.leg_points(leg);
+        let points = path
+            .iter()
+            .map(|location| Point::from_location(location, &self.origin))
+            .collect();
+        Leg {
+            from: Some(leg.to),
+            start: leg.end,
+            heading: arrival_heading(&start, &points),
+            depart: leg.depart + path_length(&start, &points) / self.speed,
+            ..*leg
         }
+    }
 
-        for node in &self.nodes {
-            let loc = node.borrow().origin.to_location(&self.origin);
-            println!("{}, {}", loc.lat_degree(), loc.lon_degree());
-            // let loc = node.borrow().origin;
-            // println!("{}, {}", loc.x, loc.y);
-            if node.borrow().height > 0f32 {
-                let mut current = node.borrow().left_ring.clone();
-                loop {
-                    let ref mut vertex = current.clone();
-                    //print!("{:?}\n", current.borrow().index);
-                    let index = match vertex.borrow().next {
-                        Some(ref vert) => vert.borrow().index,
-                        None => panic!("Next points to null"),
-                    };
-                    if index != HEADER_VERTEX_INDEX {
-                        println!("vertex {}", vertex.borrow().location.to_location(&self.origin));
-                    } else {
-                        break;
-                    }
-                    current = match vertex.borrow().next {
-                        Some(ref v) => v.clone(),
-                        None => panic!("Next points to null"),
-                    };
-                }
+    // Start and end of leg relative to the origin
+    fn leg_points(&self, leg: &Leg) -> (Point, Point) {
+        (
+            Point::from_location(&leg.start, &self.origin),
+            Point::from_location(&leg.end, &self.origin),
+        )
+    }
+
+    fn to_locations(&self, path: LinkedList<Point>) -> LinkedList<Location> {
+        path.iter()
+            .map(|point| point.to_location(&self.origin))
+            .collect()
+    }
+
+    // Give the points of a leg leaving start altitudes changing steadily toward the altitude of
+    // its last point, within the climb and descent limits, over any obstacle flown across and
+    // between the floor and ceiling of the flyzones
+    fn assign_altitudes(
+        &self,
+        start: &Location,
+        leg: LinkedList<Location>,
+    ) -> LinkedList<Location> {
+        let mut prev = Point::from_location(start, &self.origin);
+        let mut steps = Vec::new();
+        for location in &leg {
+            let point = Point::from_location(location, &self.origin);
+            steps.push((prev, point, prev.distance(&point), false));
+            prev = point;
+        }
+        let lengths: Vec<f32> = steps.iter().map(|step| step.2).collect();
+        let (floors, bands) = self.leg_limits(start.alt(), prev.z, &steps);
+        let altitudes = self.altitudes(start.alt(), prev.z, &lengths, &floors, &bands);
+        leg.iter()
+            .zip(altitudes)
+            .map(|(location, z)| Location::from_radians(location.lat(), location.lon(), z))
+            .collect()
+    }
+
+    // Altitudes at the end of each step of a leg from altitude start to altitude end, the steps
+    // being lengths meters long and flown at or above floors, each point kept within its band of
+    // flyzone altitudes
+    // Where both cannot be met, the climb and descent limits win over the floors and bands
+    fn altitudes(
+        &self,
+        start: f32,
+        end: f32,
+        lengths: &[f32],
+        floors: &[f32],
+        bands: &[(f32, f32)],
+    ) -> Vec<f32> {
+        let n = lengths.len();
+        if n == 0 {
+            return Vec::new();
+        }
+        let (climb, descent) = (self.max_angle_ascent.tan(), self.max_angle_descent.tan());
+        let total: f32 = lengths.iter().sum();
+        // each point is above the steps arriving at and leaving it
+        let floor = |i: usize| {
+            floors[i]
+                .max(floors.get(i + 1).cloned().unwrap_or(f32::MIN))
+                .max(bands[i].0)
+        };
+        let mut flown = 0f32;
+        let mut altitudes: Vec<f32> = lengths
+            .iter()
+            .enumerate()
+            .map(|(i, length)| {
+                flown += length;
+                let z = if total > 0f32 {
+                    start + (end - start) * flown / total
+                } else {
+                    end
+                };
+                z.max(floor(i)).min(bands[i].1)
+            })
+            .collect();
+        altitudes[n - 1] = end;
+        // climb early enough for what is ahead and descend late enough to reach it
+        for i in (0..n - 1).rev() {
+            let next = altitudes[i + 1];
+            altitudes[i] = altitudes[i]
+                .max(next - climb * lengths[i + 1])
+                .min(next - descent * lengths[i + 1])
+                .max(floor(i))
+                .min(bands[i].1);
+        }
+        let mut prev = start;
+        for i in 0..n - 1 {
+            altitudes[i] = altitudes[i]
+                .min(prev + climb * lengths[i])
+                .max(prev + descent * lengths[i]);
+            prev = altitudes[i];
+        }
+        altitudes
+    }
+
+    // Floors of the steps of a leg and altitude bands of the points they lead to, each step
+    // going from a to b over length meters and arcs never flying over anything
+    // Floating obstacles are passed under or over depending on the altitude the step would have
+    // changing steadily from start to end
+    fn leg_limits(
+        &self,
+        start: f32,
+        end: f32,
+        steps: &[(Point, Point, f32, bool)],
+    ) -> (Vec<f32>, Vec<(f32, f32)>) {
+        let total: f32 = steps.iter().map(|step| step.2).sum();
+        let (mut floors, mut bands) = (Vec::new(), Vec::new());
+        let mut flown = 0f32;
+        for (i, &(ref a, ref b, length, arc)) in steps.iter().enumerate() {
+            bands.push(self.altitude_band(b));
+            if arc {
+                floors.push(f32::MIN);
+                flown += length;
+                continue;
+            }
+            let z = if total > 0f32 {
+                start + (end - start) * (flown + length / 2f32) / total
+            } else {
+                end
+            };
+            flown += length;
+            let (floor, ceiling) = self.floating_limits(a, b, z);
+            floors.push(self.flyover_height(a, b).max(floor));
+            // both ends of the step stay under what it passes under
+            bands[i].1 = bands[i].1.min(ceiling);
+            if i > 0 {
+                bands[i - 1].1 = bands[i - 1].1.min(ceiling);
             }
         }
+        (floors, bands)
+    }
 
-        //A* algorithm - find shortest path from plane to destination
-        while let Some(cur) = open_list.pop() {
-            // println!("current vertex {}", cur.borrow());
-            if cur.borrow().index == END_VERTEX_INDEX {
-                Node::remove_extra_vertices(vertices_to_remove);
-                return Some(self.generate_waypoint(cur));
+    // Height of the highest obstacle the segment from a to b crosses, the lowest altitude if none
+    fn flyover_height(&self, a: &Point, b: &Point) -> f32 {
+        let mut height = f32::MIN;
+        for obstacle in self.obstacles.iter().filter(|o| !o.is_moving()) {
+            let buffer = self.obstacle_buffer(obstacle);
+            let crosses = match obstacle.shape {
+                Shape::Prism(ref footprint) => self.prism_crossing(a, b, footprint).is_some(),
+                _ => !obstacle.is_floating() && self.crosses_circle(a, b, obstacle),
+            };
+            if crosses {
+                height = height.max(obstacle.height + buffer);
             }
-            closed_set.insert(cur.borrow().index);
+        }
+        height
+    }
+
+    // Lowest and highest altitudes keeping the segment from a to b over or under the floating
+    // obstacles it crosses, over the ones whose middle is below altitude z
+    fn floating_limits(&self, a: &Point, b: &Point, z: f32) -> (f32, f32) {
+        let (mut floor, mut ceiling) = (f32::MIN, f32::MAX);
+        for obstacle in self
+            .obstacles
+            .iter()
+            .filter(|o| !o.is_moving() && o.is_floating())
+        {
+            if !self.crosses_circle(a, b, obstacle) {
+                continue;
+            }
+            let buffer = self.obstacle_buffer(obstacle);
+            if z < (obstacle.base + obstacle.height) / 2f32 {
+                ceiling = ceiling.min(obstacle.base - buffer);
+            } else {
+                floor = floor.max(obstacle.height + buffer);
+            }
+        }
+        (floor, ceiling)
+    }
+
+    // Whether the segment from a to b crosses the circle around obstacle grown by its buffer
+    // Points the same distance from the center stand for an arc around it
+    fn crosses_circle(&self, a: &Point, b: &Point, obstacle: &Obstacle) -> bool {
+        let radius = obstacle.radius + self.obstacle_buffer(obstacle);
+        let center = Point::from_location(&obstacle.location, &self.origin);
+        let (from, to) = (a.distance(&center), b.distance(&center));
+        if (from - to).abs() < 0.01 && from > radius - 0.01 {
+            return false;
+        }
+        let (_, _, dist_squared, _) = intersect_distance(a, b, &center);
+        dist_squared.sqrt() < radius - 0.01
+    }
+
+    // Check if planning has run past max_process_time since get_adjust_path was called
+    fn timed_out(&self) -> bool {
+        match self.start_time.elapsed() {
+            Ok(elapsed) => elapsed >= self.max_process_time,
+            Err(_) => false,
+        }
+    }
+
+    // Extra cost from the configured weights for reaching point p on a leg from start to end,
+    // direct_path_modifier_weight penalizes straying from the straight line between them
+    fn direct_path_cost(&self, start: &Point, end: &Point, p: &Point) -> f32 {
+        let (_, _, dist_squared, _) = intersect_distance(start, end, p);
+        self.config.direct_path_modifier_weight * dist_squared.sqrt()
+    }
+
+    // Find best path using the a* algorithm
+    // Return path if found and none if any error occured, no path found or time ran out
+    fn adjust_path(
+        &self,
+        start: Point,
+        end: Point,
+        heading: Option<f32>,
+        depart: f32,
+    ) -> Option<LinkedList<Point>> {
+        let (overlay, end_vertex, parent) = self.search(start, end, heading, depart)?;
+        Some(self.generate_path(&overlay, end_vertex, &parent, &start))
+    }
 
-            let mut update_vertex = |cur_g_cost: f32, next: Rc<RefCell<Vertex>>, dist: f32| {
-                if next.borrow().index == cur.borrow().index {
-                    return;
+    // A* from start to end over the tangent graph, returning the graph with the nodes added for
+    // the search, the vertex reached at end and the parent of every vertex on the way
+    // Heading is the direction the plane is flying at start, if known, in which case the plane
+    // leaves along one of its turning circles
+    // Depart is the number of seconds from now the plane leaves start, moves are checked
+    // against moving obstacles at the time the plane makes them
+    fn search(
+        &self,
+        start: Point,
+        end: Point,
+        heading: Option<f32>,
+        depart: f32,
+    ) -> Option<(Overlay<'_>, VertexId, Vec<Option<VertexId>>)> {
+        let mut overlay = Overlay::new(&self.graph);
+        let start_vertices = self.add_start(&mut overlay, start, heading);
+        let start_nodes: Vec<NodeId> = start_vertices
+            .iter()
+            .map(|v| overlay.vertex(*v).node)
+            .collect();
+        let end_node = overlay.add_node(Node::new(end, 0f32, end.z));
+        let mut moving_nodes = Vec::new();
+        for obstacle in self.obstacles.iter().filter(|o| o.is_moving()) {
+            let node = self.predicted_node(obstacle, &start, depart);
+            moving_nodes.push(overlay.add_node(node));
+        }
+
+        //Prepare graph for A*, destinations of replanned legs are left out
+        let nodes: Vec<NodeId> = (0..self.graph.nodes.len())
+            .map(NodeId)
+            .filter(|&node| !self.graph.node(node).goal)
+            .chain(moving_nodes.iter().cloned())
+            .collect();
+        for &start_node in &start_nodes {
+            for &node in nodes.iter().chain(Some(&end_node)) {
+                if self.timed_out() {
+                    return None;
                 }
-                let new_g_cost = cur_g_cost + dist;
-                // println!("add vertex to queue {}", next.borrow());
-                {
-                    let mut next_mut = next.borrow_mut();
-                    if closed_set.contains(&next_mut.index)                                         //vertex is already explored
-                        || next_mut.sentinel                                                        //vertex is a sentinel
-                        || (open_set.contains(&next_mut.index) && new_g_cost >= next_mut.g_cost)
-                    {
-                        //vertex has been visited and the current cost is better
-                        // println!("Vertex addition skipped");
-                        return;
-                    }
-                    let new_f_cost = next_mut.g_cost
-                        + next_mut
-                            .location
-                            .distance3d(&Point::from_location(&end, &self.origin));
-                    next_mut.g_cost = new_g_cost;
-                    next_mut.f_cost = new_f_cost;
-                    next_mut.parent = Some(cur.clone());
+                self.connect(&mut overlay, start_node, node);
+            }
+        }
+        for &node in &nodes {
+            if self.timed_out() {
+                return None;
+            }
+            self.connect(&mut overlay, node, end_node);
+        }
+        // hug predicted obstacles on the way to and from the rest of the graph
+        for &moving in &moving_nodes {
+            for &node in &nodes {
+                if node != moving {
+                    self.connect(&mut overlay, moving, node);
+                    self.connect(&mut overlay, node, moving);
                 }
-                open_list.push(next.clone());
-                open_set.insert(next.borrow().index);
-            };
+            }
+        }
 
-            let mut cur_vertex = cur.borrow();
-            let g_cost = cur_vertex.g_cost;
-            if let Some(ref connection) = cur_vertex.connection {
-                let mut next = connection.neighbor.clone();
-                let dist = connection.distance;
-                update_vertex(g_cost, next, dist);
+        //A* algorithm - find shortest path from plane to destination
+        let size = overlay.num_vertices();
+        let mut g_cost = vec![f32::MAX; size];
+        let mut parent: Vec<Option<VertexId>> = vec![None; size];
+        let mut closed = vec![false; size];
+        let mut arrival = vec![depart; size];
+        // highest the plane can be on reaching each vertex, climbing as steeply as it can
+        let mut altitude = vec![start.z; size];
+        let mut open_list = BinaryHeap::new();
+        for &v in &start_vertices {
+            g_cost[v.0] = 0f32;
+            open_list.push((Reverse(OrderedFloat(start.distance(&end))), v));
+        }
+        while let Some((_, cur)) = open_list.pop() {
+            if self.timed_out() {
+                return None;
+            }
+            if closed[cur.0] {
+                continue;
+            }
+            closed[cur.0] = true;
+            let vertex = overlay.vertex(cur);
+            let location = vertex.location.to_location(&self.origin);
+            self.observer.node_expanded(&location, g_cost[cur.0]);
+            if vertex.node == end_node {
+                return Some((overlay, cur, parent));
             }
 
-            let mut weight = cur_vertex.get_neighbor_weight();
-            if let Some(ref next_vertex) = cur_vertex.next {
-                let mut next = next_vertex.clone();
-                if (next.borrow().index != HEADER_VERTEX_INDEX) {
-                    update_vertex(g_cost, next, weight);
+            // neighbors with the cost and distance to reach them and the height to fly over
+            let mut neighbors = Vec::new();
+            if let Some(ref connection) = vertex.connection {
+                neighbors.push((
+                    connection.neighbor,
+                    connection.distance,
+                    connection.distance,
+                    connection.threshold,
+                ));
+            }
+            if let Some((next, arc)) = overlay.next(cur) {
+                // turning on a start circle moves the plane away from its heading
+                let on_start = start_nodes.contains(&vertex.node) && vertex.radius > 0f32;
+                let turn = if on_start {
+                    self.config.heading_modifier_weight * arc / vertex.radius
                 } else {
-                    weight += next.borrow().get_neighbor_weight();
-                    match next.borrow().next {
-                        Some(ref skip_next) => update_vertex(g_cost, skip_next.clone(), weight),
-                        None => panic!("broken chain"),
-                    }
-                    //update_vertex(g_cost, );
+                    0f32
+                };
+                if !on_start || self.start_turn_clear(&overlay, cur, next) {
+                    neighbors.push((next, arc + turn, arc, 0f32));
+                }
+            }
+            for (next, cost, distance, threshold) in neighbors {
+                let next_vertex = overlay.vertex(next);
+                if closed[next.0] || next_vertex.sentinel || overlay.node(next_vertex.node).goal {
+                    continue;
                 }
+                let new_g_cost = g_cost[cur.0]
+                    + cost
+                    + self.direct_path_cost(&start, &end, &next_vertex.location);
+                if new_g_cost >= g_cost[next.0] {
+                    continue;
+                }
+                // edges with a threshold fly over an obstacle at least that high
+                if threshold > 0f32
+                    && !self.can_fly_over(
+                        &vertex.location,
+                        &next_vertex.location,
+                        altitude[cur.0],
+                        &end,
+                    )
+                {
+                    continue;
+                }
+                if !moving_nodes.is_empty()
+                    && !self.clear_of_moving(&overlay, cur, next, arrival[cur.0])
+                {
+                    continue;
+                }
+                g_cost[next.0] = new_g_cost;
+                arrival[next.0] = arrival[cur.0] + distance / self.speed;
+                altitude[next.0] = altitude[cur.0] + self.max_angle_ascent.tan() * distance;
+                parent[next.0] = Some(cur);
+                let new_f_cost = new_g_cost + next_vertex.location.distance(&end);
+                open_list.push((Reverse(OrderedFloat(new_f_cost)), next));
             }
         }
         None
-        //TODO: Clean up the graph before we finish
     }
 
-    fn generate_waypoint(&self, end_vertex: Rc<RefCell<Vertex>>) -> LinkedList<Waypoint> {
-        let mut waypoint_list = LinkedList::new();
+    // Add the nodes the plane leaves start from and return its vertices on them
+    // With a heading, the plane turns on one of two circles of the turning radius tangent to
+    // its heading; without one it can leave in any direction
+    fn add_start(
+        &self,
+        overlay: &mut Overlay,
+        start: Point,
+        heading: Option<f32>,
+    ) -> Vec<VertexId> {
+        let mut start_vertices = Vec::new();
+        match heading {
+            Some(h) => {
+                let radius = self.turning_radius;
+                for left in [true, false] {
+                    let side = if left { 1f32 } else { -1f32 };
+                    let center = Point::new(
+                        start.x - side * radius * h.sin(),
+                        start.y + side * radius * h.cos(),
+                        start.z,
+                    );
+                    let node = overlay.add_node(Node::new(center, radius, start.z));
+                    let vertex = overlay.add_vertex(node, h - side * PI / 2f32, left, None);
+                    start_vertices.push(vertex);
+                }
+            }
+            None => {
+                let node = overlay.add_node(Node::new(start, 0f32, start.z));
+                start_vertices.push(overlay.add_vertex(node, 0f32, true, None));
+            }
+        }
+        start_vertices
+    }
+
+    // Check the move from vertex cur to vertex next, along a tangent or around a node, against
+    // moving obstacles when the plane leaves at time depart
+    fn clear_of_moving(
+        &self,
+        overlay: &Overlay,
+        cur: VertexId,
+        next: VertexId,
+        depart: f32,
+    ) -> bool {
+        let (from, to) = (overlay.vertex(cur), overlay.vertex(next));
+        let tangent = match from.connection {
+            Some(ref connection) => connection.neighbor == next,
+            None => false,
+        };
+        if tangent || from.radius == 0f32 {
+            let validity = self.valid_path_at(&from.location, &to.location, depart);
+            return !matches!(validity, PathValidity::Invalid);
+        }
+        let mut time = depart;
+        arc_points(overlay, cur, next).windows(2).all(|step| {
+            let validity = self.valid_path_at(&step[0], &step[1], time);
+            time += step[0].distance(&step[1]) / self.speed;
+            !matches!(validity, PathValidity::Invalid)
+        })
+    }
+
+    // Check the turn from vertex cur to vertex next on a start circle against the flyzones and
+    // static obstacles, the plane turns there at the altitude it starts at
+    fn start_turn_clear(&self, overlay: &Overlay, cur: VertexId, next: VertexId) -> bool {
+        arc_points(overlay, cur, next)
+            .windows(2)
+            .all(|step| !matches!(self.valid_path(&step[0], &step[1]), PathValidity::Invalid))
+    }
+
+    // Check a flyover edge from a to b, the plane being at most altitude high at a
+    // Climbing as steeply as it can, the plane must be above every obstacle the edge crosses
+    // where it enters it, and able to descend from its top to end after leaving it
+    fn can_fly_over(&self, a: &Point, b: &Point, altitude: f32, end: &Point) -> bool {
+        let (climb, descent) = (self.max_angle_ascent.tan(), self.max_angle_descent.tan());
+        self.obstacle_spans(a, b)
+            .into_iter()
+            .all(|(height, entry, exit)| {
+                let left = (a.distance(b) - exit).max(0f32) + b.distance(end);
+                altitude + climb * entry >= height && height + descent * left <= end.z
+            })
+    }
+
+    // Check a flyover segment flown with its altitude changing steadily from a to b, the plane
+    // must be above every obstacle where it enters and leaves it
+    fn flies_over(&self, a: &Point, b: &Point) -> bool {
+        let length = a.distance(b).max(0.01);
+        let altitude = |s: f32| a.z + (b.z - a.z) * s.min(length) / length;
+        self.obstacle_spans(a, b)
+            .into_iter()
+            .all(|(height, entry, exit)| altitude(entry) >= height && altitude(exit) >= height)
+    }
+
+    // Static obstacles under the segment from a to b with their height grown by the buffer, and
+    // the distances from a where the segment enters and leaves them
+    fn obstacle_spans(&self, a: &Point, b: &Point) -> Vec<(f32, f32, f32)> {
+        self.obstacles
+            .iter()
+            .filter(|o| !o.is_moving() && !o.is_floating())
+            .filter_map(|obstacle| {
+                let buffer = self.obstacle_buffer(obstacle);
+                let (radius, height) = (obstacle.radius + buffer, obstacle.height + buffer);
+                let (entry, exit) = match obstacle.shape {
+                    Shape::Cylinder | Shape::Sphere => {
+                        let center = Point::from_location(&obstacle.location, &self.origin);
+                        let (x, y, dist_squared, _) = intersect_distance(a, b, &center);
+                        if dist_squared >= (radius - 0.01).powi(2) {
+                            return None;
+                        }
+                        let closest = a.distance(&Point::new(x, y, 0f32));
+                        let half = (radius.powi(2) - dist_squared).sqrt();
+                        ((closest - half).max(0f32), closest + half)
+                    }
+                    Shape::Prism(ref footprint) => {
+                        let (entry, exit) = self.prism_crossing(a, b, footprint)?;
+                        (entry * a.distance(b), exit * a.distance(b))
+                    }
+                };
+                Some((height, entry, exit))
+            })
+            .collect()
+    }
+
+    // Add the valid tangents from node a to node b
+    fn connect(&self, overlay: &mut Overlay, a: NodeId, b: NodeId) {
+        if overlay.node(a).removed || overlay.node(b).removed {
+            return;
+        }
+        let (paths, _) = self.find_path(overlay.node(a), overlay.node(b));
+        let theta0 = overlay.bearing(a, b);
+        for (alpha, beta, distance, threshold) in paths {
+            overlay.add_edge(a, theta0 + alpha, b, theta0 + beta, distance, threshold);
+        }
+    }
+
+    // Points where the path to end vertex arrives at or departs from a node, excluding the start
+    // Points within a meter of the previous one are merged
+    fn generate_path(
+        &self,
+        overlay: &Overlay,
+        end_vertex: VertexId,
+        parent: &[Option<VertexId>],
+        start: &Point,
+    ) -> LinkedList<Point> {
+        let tangent = |from: VertexId, to: VertexId| match overlay.vertex(from).connection {
+            Some(ref connection) => connection.neighbor == to,
+            None => false,
+        };
+        let mut path = LinkedList::new();
+        path.push_front(overlay.vertex(end_vertex).location);
         let mut cur_vertex = end_vertex;
-        let mut index = END_VERTEX_INDEX;
-        while index != START_VERTEX_INDEX {
-            let loc = cur_vertex.borrow().location.to_location(&self.origin);
-            let radius = cur_vertex.borrow().radius;
-            waypoint_list.push_front(Waypoint::new(1, loc, radius));
-            println!("{:?}", cur_vertex);
-            let parent = match cur_vertex.borrow().parent {
-                Some(ref cur_parent) => cur_parent.clone(),
-                None => panic!("Missing a parent without reaching start point"),
+        while let Some(prev) = parent[cur_vertex.0] {
+            let child = cur_vertex;
+            cur_vertex = prev;
+            let location = overlay.vertex(cur_vertex).location;
+            let merged = match path.front() {
+                Some(next) => location.distance(next) < 1f32,
+                None => false,
+            };
+            let turn = match parent[cur_vertex.0] {
+                Some(prev) => tangent(prev, cur_vertex) || tangent(cur_vertex, child),
+                None => false,
             };
-            cur_vertex = parent;
-            index = cur_vertex.borrow().index;
+            if turn && !merged && location.distance(start) >= 1f32 {
+                path.push_front(location);
+            }
         }
-        waypoint_list
+        path
     }
+
     pub fn set_process_time(&mut self, max_process_time: u32) {
         self.max_process_time = Duration::from_secs(max_process_time as u64);
     }
 
-    pub fn set_flyzone(&mut self, flyzone: Vec<Vec<Location>>) {
+    // Plan legs at the same time on multiple threads, legs after the first then leave
+    // waypoints in any direction
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    // Keep the search of each leg between calls to get_adjust_path and repair it as the plane
+    // moves and obstacles change; legs are then planned one after another
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+    }
+
+    // Join the points of the path with Dubins paths the plane can fly within its turning radius,
+    // arcs are given as points no more than 22.5 degrees of turn apart
+    pub fn set_dubins(&mut self, dubins: bool) {
+        self.dubins = dubins;
+    }
+
+    pub fn set_flyzone<F: Into<Flyzone>>(&mut self, flyzone: Vec<F>) {
+        if let Err(e) = self.try_set_flyzone(flyzone) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_set_flyzone<F: Into<Flyzone>>(
+        &mut self,
+        flyzone: Vec<F>,
+    ) -> Result<(), PathfinderError> {
+        let flyzone: Vec<Flyzone> = flyzone.into_iter().map(Into::into).collect();
+        self.validate_flyzones(&flyzone)?;
         self.flyzones = flyzone;
         self.build_graph();
+        Ok(())
+    }
+
+    // Areas given by their points in order that the plane must never fly through, at any altitude
+    pub fn set_exclusions(&mut self, exclusions: Vec<Vec<Location>>) {
+        if let Err(e) = self.try_set_exclusions(exclusions) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_set_exclusions(
+        &mut self,
+        exclusions: Vec<Vec<Location>>,
+    ) -> Result<(), PathfinderError> {
+        self.validate_exclusions(&exclusions)?;
+        self.exclusions = exclusions;
+        // kept for init otherwise
+        if self.initialized {
+            self.build_graph();
+        }
+        Ok(())
     }
 
     pub fn set_obstacles(&mut self, obstacles: Vec<Obstacle>) {
+        if let Err(e) = self.try_set_obstacles(obstacles) {
+            panic!("{}", e);
+        }
+    }
+
+    pub fn try_set_obstacles(&mut self, obstacles: Vec<Obstacle>) -> Result<(), PathfinderError> {
+        // the graph cannot be built without a flyzone
+        self.validate_flyzones(&self.flyzones)?;
+        obstacles.iter().try_for_each(validate_obstacle)?;
         self.obstacles = obstacles;
+        self.assign_obstacle_ids();
         self.build_graph();
+        Ok(())
+    }
+
+    // Add an obstacle without rebuilding the graph, only tangents touching it are computed
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) -> Result<ObstacleId, PathfinderError> {
+        let id = self.new_obstacle_id();
+        self.insert_obstacle(id, obstacle)?;
+        Ok(id)
+    }
+
+    // Remove an obstacle, restoring the tangents it blocked
+    pub fn remove_obstacle(&mut self, id: ObstacleId) -> Result<Obstacle, PathfinderError> {
+        if !self.initialized {
+            return Err(PathfinderError::Uninitialized);
+        }
+        let index = match self.obstacle_ids.iter().position(|&other| other == id) {
+            Some(index) => index,
+            None => return Err(PathfinderError::UnknownObstacle(id)),
+        };
+        self.obstacle_ids.remove(index);
+        let nodes = self.obstacle_nodes.remove(index);
+        let obstacle = self.obstacles.remove(index);
+        self.prepared = false;
+        if !nodes.is_empty() {
+            self.remove_obstacle_node(&nodes, &obstacle);
+        }
+        self.trim_changes();
+        Ok(obstacle)
+    }
+
+    // Move or resize an obstacle, keeping its id
+    pub fn update_obstacle(
+        &mut self,
+        id: ObstacleId,
+        obstacle: Obstacle,
+    ) -> Result<(), PathfinderError> {
+        validate_obstacle(&obstacle)?;
+        self.remove_obstacle(id)?;
+        self.insert_obstacle(id, obstacle)
+    }
+
+    fn insert_obstacle(
+        &mut self,
+        id: ObstacleId,
+        obstacle: Obstacle,
+    ) -> Result<(), PathfinderError> {
+        if !self.initialized {
+            return Err(PathfinderError::Uninitialized);
+        }
+        validate_obstacle(&obstacle)?;
+        let moving = obstacle.is_moving();
+        self.obstacles.push(obstacle);
+        self.obstacle_ids.push(id);
+        self.prepared = false;
+        let nodes = if moving {
+            Vec::new()
+        } else {
+            self.insert_obstacle_node()
+        };
+        self.obstacle_nodes.push(nodes);
+        self.trim_changes();
+        Ok(())
+    }
+
+    fn assign_obstacle_ids(&mut self) {
+        self.obstacle_ids = (0..self.obstacles.len())
+            .map(|_| self.new_obstacle_id())
+            .collect();
+    }
+
+    fn new_obstacle_id(&mut self) -> ObstacleId {
+        self.next_obstacle_id += 1;
+        ObstacleId(self.next_obstacle_id - 1)
     }
 
     pub fn get_buffer_size(&self) -> f32 {
@@ -358,24 +1232,202 @@ impl Pathfinder {
         self.max_process_time.as_secs() as u32
     }
 
-    pub fn get_flyzone(&mut self) -> &Vec<Vec<Location>> {
+    pub fn get_turning_radius(&self) -> f32 {
+        self.turning_radius
+    }
+
+    pub fn get_max_angle_ascent(&self) -> f32 {
+        self.max_angle_ascent
+    }
+
+    pub fn get_max_angle_descent(&self) -> f32 {
+        self.max_angle_descent
+    }
+
+    pub fn get_min_buffer(&self) -> f32 {
+        self.min_buffer
+    }
+
+    pub fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    pub fn is_incremental(&self) -> bool {
+        self.incremental
+    }
+
+    pub fn is_dubins(&self) -> bool {
+        self.dubins
+    }
+
+    // Whether the last adjusted path covers every leg, false if it was cut short by
+    // max_process_time and holds only the legs solved in time
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn get_flyzone(&mut self) -> &Vec<Flyzone> {
         &self.flyzones
     }
 
+    pub fn get_exclusions(&self) -> &Vec<Vec<Location>> {
+        &self.exclusions
+    }
+
     pub fn get_obstacle_list(&self) -> &Vec<Obstacle> {
         &self.obstacles
     }
+
+    // Ids of the obstacles in the same order as get_obstacle_list
+    pub fn get_obstacle_ids(&self) -> &Vec<ObstacleId> {
+        &self.obstacle_ids
+    }
+
+    pub fn set_config(&mut self, config: PathfinderConfig) {
+        self.config = config;
+    }
+
+    pub fn get_config(&self) -> &PathfinderConfig {
+        &self.config
+    }
+
+    // Find paths with planner instead of A* on the tangent graph
+    pub fn set_planner<P: Planner + 'static>(&mut self, planner: P) {
+        self.planner = Box::new(planner);
+        self.prepared = false;
+    }
+
+    pub fn get_planner(&self) -> &dyn Planner {
+        &*self.planner
+    }
+
+    // Receive planner events, the default observer ignores them
+    pub fn set_observer<O: PlannerObserver + 'static>(&mut self, observer: O) {
+        self.observer = Box::new(observer);
+    }
+}
+
+// Points around the node of vertex cur to vertex next, in steps short enough to stay close to
+// the circle, both ends included
+fn arc_points(overlay: &Overlay, cur: VertexId, next: VertexId) -> Vec<Point> {
+    let (from, to) = (overlay.vertex(cur), overlay.vertex(next));
+    let sweep = if from.angle > 0f32 {
+        to.angle - from.angle
+    } else {
+        from.angle - to.angle
+    }
+    .rem_euclid(2f32 * PI);
+    let steps = (sweep / (PI / 8f32)).ceil().max(1f32) as usize;
+    let step = sweep / steps as f32 * from.angle.signum();
+    let node = overlay.node(from.node);
+    let mut points = vec![from.location];
+    for k in 1..=steps {
+        points.push(Point::from_node_and_angle(
+            node,
+            from.angle + step * k as f32,
+        ));
+    }
+    points
+}
+
+// Length of a leg flown straight between its points
+fn path_length(start: &Point, leg: &LinkedList<Point>) -> f32 {
+    let mut length = 0f32;
+    let mut prev = start;
+    for point in leg {
+        length += prev.distance(point);
+        prev = point;
+    }
+    length
+}
+
+// Check the buffer an obstacle has of its own
+fn validate_obstacle(obstacle: &Obstacle) -> Result<(), PathfinderError> {
+    if obstacle.base.is_nan() || obstacle.base > obstacle.height {
+        return Err(PathfinderError::InvalidParameter {
+            name: "base",
+            value: obstacle.base,
+        });
+    }
+    if let Shape::Prism(ref footprint) = obstacle.shape {
+        let polygon: Vec<Point> = footprint
+            .iter()
+            .map(|corner| Point::from_location(corner, &obstacle.location))
+            .collect();
+        let n = polygon.len();
+        // a simple polygon of at least 3 points, as flyzones are
+        let crossing = (0..n).any(|i| {
+            (i + 2..n).any(|j| {
+                (j + 1) % n != i
+                    && intersect(
+                        &polygon[i],
+                        &polygon[i + 1],
+                        &polygon[j],
+                        &polygon[(j + 1) % n],
+                    )
+            })
+        });
+        if n < 3 || crossing {
+            return Err(PathfinderError::InvalidParameter {
+                name: "footprint",
+                value: n as f32,
+            });
+        }
+    }
+    match obstacle.buffer {
+        Some(buffer) if buffer.is_nan() || buffer < 0f32 || buffer.is_infinite() => {
+            Err(PathfinderError::InvalidParameter {
+                name: "buffer",
+                value: buffer,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+// Leg leaving start, before the waypoint it goes to is set
+fn first_leg(start: Location, heading: Option<f32>) -> Leg {
+    Leg {
+        from: None,
+        to: 0,
+        start: start,
+        end: start,
+        heading: heading,
+        depart: 0f32,
+    }
+}
+
+// Heading in radians, counter-clockwise from east, of the last segment of a leg
+fn arrival_heading(start: &Point, leg: &LinkedList<Point>) -> Option<f32> {
+    let mut points = leg.iter().rev();
+    let end = points.next()?;
+    let before = points.next().unwrap_or(start);
+    Some((end.y - before.y).atan2(end.x - before.x))
+}
+
+// Direction the plane is flying in radians counter-clockwise from east, none if yaw is unknown
+fn plane_heading(plane: &Plane) -> Option<f32> {
+    if plane.yaw < 0f32 {
+        None
+    } else {
+        Some((90f32 - plane.yaw).to_radians())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fixture::*;
     use graph::Point;
 
     #[test]
     #[should_panic]
     fn invalid_flyzones_test() {
-        Pathfinder::new().init(1f32, vec![], Vec::new())
+        Pathfinder::new().init(1f32, Vec::<Flyzone>::new(), Vec::new())
     }
 
     #[test]
@@ -396,4 +1448,246 @@ mod tests {
         let mut pathfinder = Pathfinder::create(1f32, test_flyzone, Vec::new());
         assert!(pathfinder.invalid_flyzone(0));
     }
+
+    #[test]
+    fn init_error_test() {
+        let origin = Location::from_degrees(0f64, 0f64, 0f32);
+        let a = Point::new(0f32, 0f32, 10f32).to_location(&origin);
+        let b = Point::new(20f32, 0f32, 10f32).to_location(&origin);
+        let c = Point::new(20f32, 20f32, 10f32).to_location(&origin);
+        let d = Point::new(0f32, 20f32, 10f32).to_location(&origin);
+        let mut pathfinder = Pathfinder::new();
+        assert_eq!(
+            pathfinder.try_init(1f32, Vec::<Flyzone>::new(), Vec::new()),
+            Err(PathfinderError::NoFlyzone)
+        );
+        assert_eq!(
+            pathfinder.try_init(1f32, vec![vec![a, b, c, d], vec![a, b]], Vec::new()),
+            Err(PathfinderError::TooFewPoints {
+                flyzone: 1,
+                points: 2
+            })
+        );
+        assert_eq!(
+            pathfinder.try_init(1f32, vec![vec![a, b, d, c]], Vec::new()),
+            Err(PathfinderError::SelfIntersectingFlyzone {
+                flyzone: 0,
+                edges: (1, 3)
+            })
+        );
+        assert_eq!(
+            pathfinder.try_set_obstacles(Vec::new()),
+            Err(PathfinderError::NoFlyzone)
+        );
+        assert!(pathfinder
+            .try_init(1f32, vec![vec![a, b, c, d]], Vec::new())
+            .is_ok());
+        assert_eq!(
+            pathfinder.try_set_flyzone(vec![vec![a, b, d, c]]),
+            Err(PathfinderError::SelfIntersectingFlyzone {
+                flyzone: 0,
+                edges: (1, 3)
+            })
+        );
+        assert_eq!(pathfinder.get_flyzone()[0].boundary, vec![a, b, c, d]);
+    }
+
+    #[test]
+    fn uninitialized_test() {
+        let mut pathfinder = Pathfinder::new();
+        let plane = Plane::from_degrees(0f64, 0f64, 10f32);
+        assert_eq!(
+            pathfinder
+                .try_get_adjust_path(plane, LinkedList::new())
+                .err(),
+            Some(PathfinderError::Uninitialized)
+        );
+    }
+
+    #[test]
+    fn timeout_test() {
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+        pathfinder.set_process_time(0);
+        let plane = Plane::new(at(50f32, 50f32, 10f32));
+        let mut waypoints = LinkedList::new();
+        waypoints.push_back(Waypoint::new(0, at(300f32, 300f32, 10f32), 1f32));
+        assert_eq!(
+            pathfinder
+                .try_get_adjust_path(plane, waypoints.clone())
+                .err(),
+            Some(PathfinderError::Timeout)
+        );
+        assert!(!pathfinder.is_complete());
+        assert!(pathfinder.get_adjust_path(plane, waypoints).is_empty());
+    }
+
+    #[test]
+    fn weighted_cost_test() {
+        let mut pathfinder = Pathfinder::new();
+        pathfinder.set_config(PathfinderConfig {
+            direct_path_modifier_weight: 0.5f32,
+            heading_modifier_weight: 2f32,
+        });
+        let start = Point::new(0f32, 0f32, 0f32);
+        let end = Point::new(100f32, 0f32, 0f32);
+        let p = Point::new(50f32, 10f32, 0f32);
+        assert_eq!(pathfinder.direct_path_cost(&start, &end, &p), 5f32);
+    }
+
+    #[test]
+    fn send_test() {
+        fn assert_send<T: Send + Sync>() {}
+        assert_send::<Pathfinder>();
+    }
+
+    #[test]
+    fn avoid_obstacle_test() {
+        let obstacle = center_obstacle(50f32, 200f32);
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![obstacle]);
+        let (plane, waypoints) = crossing(50f32, 50f32);
+        let origin = pathfinder.origin;
+        let mut points = vec![project(&pathfinder, &plane.location)];
+        for wp in pathfinder.try_get_adjust_path(plane, waypoints).unwrap() {
+            assert_eq!(wp.index, 0);
+            points.push(Point::from_location(&wp.location, &origin));
+        }
+        assert!(points.len() > 2);
+        let end = project(&pathfinder, &at(350f32, 200f32, 0f32));
+        assert!(points[points.len() - 1].distance(&end) < 0.01);
+        let center = project(&pathfinder, &at(200f32, 200f32, 200f32));
+        // segments between two points on the obstacle follow its edge
+        let on_edge = |p: &Point| (p.distance(&center) - 50f32).abs() < 0.01;
+        for i in 1..points.len() {
+            let (a, b) = (&points[i - 1], &points[i]);
+            let (_, _, dist, _) = intersect_distance(a, b, &center);
+            assert!(dist.sqrt() > 49.9 || (on_edge(a) && on_edge(b)));
+        }
+    }
+
+    #[test]
+    fn altitude_test() {
+        let obstacle = Obstacle::new(at(200f32, 200f32, 10f32), 50f32, 200f32);
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![obstacle]);
+        let (plane, waypoints) = crossing(100f32, 150f32);
+        let path = pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+        assert!(path.len() > 2);
+        // avoidance points climb from the plane to the waypoint instead of taking the
+        // altitude of the obstacle
+        let mut prev = 100f32;
+        for wp in path {
+            assert!(wp.location.alt() > prev && wp.location.alt() <= 150f32);
+            prev = wp.location.alt();
+        }
+        assert_eq!(prev, 150f32);
+
+        // the climb limit keeps the first point under what follows, the descent limit holds the
+        // third one up
+        let floors = [f32::MIN, 120f32, f32::MIN, f32::MIN];
+        let bands = [(f32::MIN, f32::MAX); 4];
+        let lengths = [10f32, 10f32, 10f32, 100f32];
+        let altitudes = pathfinder.altitudes(100f32, 0f32, &lengths, &floors, &bands);
+        let step = 10f32 * (PI / 3f32).tan();
+        assert!((altitudes[0] - (100f32 + step)).abs() < 0.01);
+        assert_eq!(altitudes[1], 120f32);
+        assert!((altitudes[2] - (120f32 - step)).abs() < 0.01);
+        assert_eq!(altitudes[3], 0f32);
+    }
+
+    #[test]
+    fn flyover_test() {
+        let mut pathfinder = PathfinderBuilder::new()
+            .max_angle_ascent(PI / 90f32)
+            .create(square_flyzone(), vec![center_obstacle(45f32, 40f32)])
+            .unwrap();
+        // high enough, the plane flies straight over the obstacle
+        assert_eq!(cross_square(&mut pathfinder, 50f32).len(), 2);
+        let origin = pathfinder.origin;
+        let point = |x: f32, z: f32| Point::from_location(&at(x, 200f32, z), &origin);
+        let (a, b) = (point(50f32, 0f32), point(350f32, 0f32));
+        assert!(pathfinder.can_fly_over(&a, &b, 50f32, &point(350f32, 50f32)));
+        // barely climbing from below it cannot get over in time
+        assert!(!pathfinder.can_fly_over(&a, &b, 30f32, &point(350f32, 50f32)));
+        // nor come down to an end just behind it
+        let near = point(260f32, 0f32);
+        assert!(!pathfinder.can_fly_over(&a, &near, 50f32, &point(260f32, 0f32)));
+        assert!(pathfinder.can_fly_over(&a, &b, 50f32, &point(350f32, 0f32)));
+    }
+
+    #[test]
+    fn start_heading_test() {
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), Vec::new());
+        pathfinder.turning_radius = 20f32;
+        // flying east, waypoint is due north
+        let plane = Plane::new(at(100f32, 100f32, 50f32)).yaw(90f32);
+        let mut waypoints = LinkedList::new();
+        waypoints.push_back(Waypoint::new(0, at(100f32, 300f32, 50f32), 1f32));
+        let path = pathfinder
+            .try_get_adjust_path(plane, waypoints)
+            .unwrap()
+            .clone();
+        assert_eq!(path.len(), 2);
+        // leaves the left turning circle centered at (100, 120)
+        let exit = project(&pathfinder, &path.front().unwrap().location);
+        let turn_center = project(&pathfinder, &at(100f32, 120f32, 0f32));
+        assert!((exit.distance(&turn_center) - 20f32).abs() < 0.01);
+        assert!(exit.x > turn_center.x);
+    }
+
+    #[test]
+    fn start_turn_test() {
+        // flying north with the waypoint behind, a pole stands where one of the turns peaks
+        for &(pole_x, center_x) in &[(220f32, 180f32), (180f32, 220f32)] {
+            let pole = Obstacle::new(at(pole_x, 220f32, 200f32), 2f32, 200f32);
+            let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![pole]);
+            pathfinder.turning_radius = 20f32;
+            let turn_center = project(&pathfinder, &at(center_x, 200f32, 0f32));
+            let plane = Plane::new(at(200f32, 200f32, 50f32)).yaw(0f32);
+            let mut waypoints = LinkedList::new();
+            waypoints.push_back(Waypoint::new(0, at(200f32, 100f32, 50f32), 1f32));
+            let projection = pathfinder.origin;
+            let path = pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+            // turns the other way
+            let exit = Point::from_location(&path.front().unwrap().location, &projection);
+            assert!((exit.distance(&turn_center) - 20f32).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn parallel_test() {
+        let obstacle = center_obstacle(50f32, 200f32);
+        let mut pathfinder = PathfinderBuilder::new()
+            .parallel(true)
+            .create(square_flyzone(), vec![obstacle])
+            .unwrap();
+        let (plane, mut waypoints) = crossing(50f32, 50f32);
+        for (i, &(x, y)) in [(200f32, 350f32), (200f32, 50f32), (50f32, 50f32)]
+            .iter()
+            .enumerate()
+        {
+            waypoints.push_back(Waypoint::new(i as u32 + 1, at(x, y, 50f32), 1f32));
+        }
+        let path: Vec<Waypoint> = pathfinder
+            .try_get_adjust_path(plane, waypoints.clone())
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        assert!(pathfinder.is_complete());
+        // every leg ends at its waypoint, in mission order
+        let ends: Vec<&Waypoint> = path
+            .windows(2)
+            .filter(|pair| pair[0].index != pair[1].index)
+            .map(|pair| &pair[0])
+            .chain(path.last())
+            .collect();
+        assert_eq!(ends.len(), waypoints.len());
+        for (end, wp) in ends.iter().zip(waypoints.iter()) {
+            assert_eq!(end.index, wp.index);
+            let (a, b) = (
+                Point::from_location(&end.location, &origin()),
+                Point::from_location(&wp.location, &origin()),
+            );
+            assert!(a.distance(&b) < 0.01);
+        }
+    }
 }
@@ -0,0 +1,32 @@
+use super::*;
+
+impl Flyzone {
+    // Flyzone without an altitude floor or ceiling
+    pub fn new(boundary: Vec<Location>) -> Self {
+        Flyzone {
+            boundary: boundary,
+            holes: Vec::new(),
+            min_alt: f32::MIN,
+            max_alt: f32::MAX,
+        }
+    }
+
+    // Altitudes in meters the plane must stay between inside the flyzone
+    pub fn altitude(mut self, min_alt: f32, max_alt: f32) -> Self {
+        self.min_alt = min_alt;
+        self.max_alt = max_alt;
+        self
+    }
+
+    // Cut out an area of the flyzone, given by its points in order
+    pub fn hole(mut self, hole: Vec<Location>) -> Self {
+        self.holes.push(hole);
+        self
+    }
+}
+
+impl From<Vec<Location>> for Flyzone {
+    fn from(boundary: Vec<Location>) -> Self {
+        Flyzone::new(boundary)
+    }
+}
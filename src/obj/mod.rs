@@ -1,5 +1,6 @@
 use ordered_float::OrderedFloat;
 
+mod flyzone;
 mod location;
 mod obstacle;
 mod plane;
@@ -12,11 +13,35 @@ pub struct Location {
     alt: OrderedFloat<f32>, //In meters
 }
 
+// Shape of an obstacle, which stands from its base to its height
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    // Circle of the radius of the obstacle around its location
+    Cylinder,
+    // Polygon given by its points in order
+    Prism(Vec<Location>),
+    // Ball of the radius of the obstacle around its location, at the altitude of the location
+    Sphere,
+}
+
 #[derive(Debug, Clone)]
 pub struct Obstacle {
     pub location: Location,
-    pub radius: f32, // In meters
+    pub radius: f32, // In meters, of the circle around the footprint of prisms
     pub height: f32, // In meters
+    pub base: f32,   // In meters, 0 when a cylinder stands on the ground
+    pub velocity: (f32, f32), // East and north, in meters per second
+    pub track: Vec<(f32, Location)>, // Predicted locations by seconds from now, in time order
+    pub buffer: Option<f32>, // In meters, the buffer of the pathfinder if none
+    pub shape: Shape,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flyzone {
+    pub boundary: Vec<Location>,   // Points in order around the flyzone
+    pub holes: Vec<Vec<Location>>, // Areas inside the boundary the plane must stay out of
+    pub min_alt: f32,              // In meters
+    pub max_alt: f32,              // In meters
 }
 
 // #TODO: standarize location name
@@ -37,4 +62,5 @@ pub struct Waypoint {
     pub index: u32,
     pub location: Location,
     pub radius: f32, // In meters
+    pub moved: bool, // Repaired by the pathfinder to a location the plane can reach
 }
@@ -1,13 +1,49 @@
 use super::*;
 
+use graph::Point;
+
 impl Obstacle {
     pub fn new(location: Location, radius: f32, height: f32) -> Self {
         Obstacle {
             location: location,
             radius: radius,
             height: height,
+            base: 0f32,
+            velocity: (0f32, 0f32),
+            track: Vec::new(),
+            buffer: None,
+            shape: Shape::Cylinder,
+        }
+    }
+
+    // Obstacle with a polygonal footprint, such as a building or a tree line
+    // Its location is the middle of the footprint and its radius reaches every corner
+    pub fn prism(footprint: Vec<Location>, height: f32) -> Self {
+        let n = footprint.len().max(1) as f64;
+        let lat = footprint.iter().map(|corner| corner.lat()).sum::<f64>() / n;
+        let lon = footprint.iter().map(|corner| corner.lon()).sum::<f64>() / n;
+        let location = Location::from_radians(lat, lon, height);
+        let radius = footprint
+            .iter()
+            .map(|corner| {
+                let point = Point::from_location(corner, &location);
+                point.x.hypot(point.y)
+            })
+            .fold(0f32, f32::max);
+        Obstacle {
+            shape: Shape::Prism(footprint),
+            ..Obstacle::new(location, radius, height)
         }
     }
+    // Airborne hazard such as a balloon, centered at the altitude of location
+    pub fn sphere(location: Location, radius: f32) -> Self {
+        Obstacle {
+            base: location.alt() - radius,
+            shape: Shape::Sphere,
+            ..Obstacle::new(location, radius, location.alt() + radius)
+        }
+    }
+
     pub fn from_degrees(lon: f64, lat: f64, radius: f32, height: f32) -> Self {
         Obstacle::new(Location::from_degrees(lon, lat, height), radius, height)
     }
@@ -15,4 +51,42 @@ impl Obstacle {
     pub fn from_radians(lon: f64, lat: f64, radius: f32, height: f32) -> Self {
         Obstacle::new(Location::from_radians(lon, lat, height), radius, height)
     }
+
+    // Moves at a constant velocity, after the end of its track if it has one
+    pub fn velocity(mut self, east: f32, north: f32) -> Self {
+        self.velocity = (east, north);
+        self
+    }
+
+    // Predicted locations paired with the number of seconds from now, in time order
+    pub fn track(mut self, track: Vec<(f32, Location)>) -> Self {
+        self.track = track;
+        self
+    }
+
+    // Keep this much away from the obstacle instead of the buffer of the pathfinder, for
+    // obstacles whose position is less certain
+    pub fn buffer(mut self, buffer: f32) -> Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    // Raise the bottom of a cylinder off the ground, leaving the airspace under it free
+    pub fn base(mut self, base: f32) -> Self {
+        self.base = base;
+        self
+    }
+
+    // Whether the plane can pass under the obstacle
+    pub fn is_floating(&self) -> bool {
+        match self.shape {
+            Shape::Cylinder => self.base > 0f32,
+            Shape::Prism(_) => false,
+            Shape::Sphere => true,
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.velocity != (0f32, 0f32) || !self.track.is_empty()
+    }
 }
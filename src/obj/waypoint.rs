@@ -6,6 +6,7 @@ impl Waypoint {
             index: index,
             location: location,
             radius: radius,
+            moved: false,
         }
     }
 
@@ -19,6 +20,9 @@ impl Waypoint {
 
     pub fn extend(&self, mut location: Location, alt: f32) -> Self {
         location.alt = alt.into();
-        Waypoint::new(self.index, location, self.radius)
+        Waypoint {
+            location: location,
+            ..self.clone()
+        }
     }
 }
@@ -0,0 +1,139 @@
+// Hooks to follow the planner as it builds the graph and searches for paths
+
+use std::collections::LinkedList;
+use std::fmt;
+
+use obj::{Location, Waypoint};
+
+// Reason a tangent between two locations was left out of the graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rejection {
+    // Crosses a flyzone boundary
+    Flyzone,
+    // Passes through an obstacle that cannot be flown over
+    Obstacle,
+    // Climbs or descends more steeply than the vehicle limits
+    ClimbAngle,
+    // Meets a moving obstacle where it will be when the plane gets there
+    MovingObstacle,
+    // Flies over an obstacle higher than the flyzone ceiling
+    Ceiling,
+    // Crosses an exclusion polygon or a hole in a flyzone
+    Exclusion,
+    // Starts or ends below the floor or above the ceiling of the flyzones around it
+    Altitude,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rejection::Flyzone => write!(f, "crosses flyzone"),
+            Rejection::Obstacle => write!(f, "blocked by obstacle"),
+            Rejection::ClimbAngle => write!(f, "exceeds climb angle"),
+            Rejection::MovingObstacle => write!(f, "meets moving obstacle"),
+            Rejection::Ceiling => write!(f, "above flyzone ceiling"),
+            Rejection::Exclusion => write!(f, "crosses exclusion polygon"),
+            Rejection::Altitude => write!(f, "outside flyzone altitudes"),
+        }
+    }
+}
+
+// Every event does nothing by default, implement the ones of interest
+pub trait PlannerObserver: Send + Sync {
+    // Visibility graph was rebuilt
+    fn graph_built(&self, nodes: usize, vertices: usize) {}
+
+    // A* expanded the vertex at location, with the cost to reach it
+    fn node_expanded(&self, location: &Location, cost: f32) {}
+
+    // Tangent between two locations was left out of the graph
+    fn edge_rejected(&self, from: &Location, to: &Location, reason: Rejection) {}
+
+    // Path to waypoint to was found; a missing start index means the plane position
+    fn leg_solved(&self, from: Option<u32>, to: u32, path: &LinkedList<Waypoint>) {}
+}
+
+// Ignores every event
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SilentObserver;
+
+impl PlannerObserver for SilentObserver {}
+
+// Prints every event to stderr
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogObserver;
+
+impl PlannerObserver for LogObserver {
+    fn graph_built(&self, nodes: usize, vertices: usize) {
+        eprintln!("graph built: {} nodes, {} vertices", nodes, vertices);
+    }
+
+    fn node_expanded(&self, location: &Location, cost: f32) {
+        eprintln!("expanded {} at cost {}", location, cost);
+    }
+
+    fn edge_rejected(&self, from: &Location, to: &Location, reason: Rejection) {
+        eprintln!("rejected {} to {}: {}", from, to, reason);
+    }
+
+    fn leg_solved(&self, from: Option<u32>, to: u32, path: &LinkedList<Waypoint>) {
+        match from {
+            Some(from) => eprint!("leg from waypoint {}", from),
+            None => eprint!("leg from plane"),
+        }
+        eprintln!(" to waypoint {} solved with {} waypoints", to, path.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+    use std::sync::{Arc, Mutex};
+    use Pathfinder;
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+        rejections: Mutex<Vec<Rejection>>,
+    }
+
+    impl PlannerObserver for Arc<RecordingObserver> {
+        fn graph_built(&self, nodes: usize, vertices: usize) {
+            self.events.lock().unwrap().push(format!("graph {}", nodes));
+        }
+
+        fn node_expanded(&self, location: &Location, cost: f32) {
+            self.events.lock().unwrap().push("expanded".to_string());
+        }
+
+        fn edge_rejected(&self, from: &Location, to: &Location, reason: Rejection) {
+            self.rejections.lock().unwrap().push(reason);
+        }
+
+        fn leg_solved(&self, from: Option<u32>, to: u32, path: &LinkedList<Waypoint>) {
+            let event = format!("leg {:?} {} {}", from, to, path.len());
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn observer_test() {
+        let obstacle = center_obstacle(50f32, 200f32);
+        let observer = Arc::new(RecordingObserver::default());
+        let mut pathfinder = Pathfinder::new();
+        pathfinder.set_observer(observer.clone());
+        pathfinder.init(1f32, square_flyzone(), vec![obstacle]);
+        let (plane, _) = crossing(50f32, 50f32);
+        let mut waypoints = LinkedList::new();
+        waypoints.push_back(Waypoint::new(3, at(350f32, 200f32, 50f32), 1f32));
+        let length = pathfinder.get_adjust_path(plane, waypoints).len();
+
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events[0], "graph 5");
+        assert!(events.iter().any(|e| e == "expanded"));
+        assert_eq!(events[events.len() - 1], format!("leg None 3 {}", length));
+        let rejections = observer.rejections.lock().unwrap();
+        assert!(rejections.contains(&Rejection::Obstacle));
+    }
+}
@@ -0,0 +1,392 @@
+// Paths as the lines and arcs the plane flies, for autopilots able to follow arcs instead of
+// only going from one point to the next
+
+use super::*;
+
+// Way the plane goes around the center of an arc, seen from above
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+// One part of a path, starting where the previous one ends
+#[derive(Clone, Copy, Debug)]
+pub enum PathSegment {
+    Line {
+        end: Location,
+    },
+    Arc {
+        center: Location,
+        radius: f32, // In meters
+        direction: Direction,
+        sweep: f32, // In radians, positive
+        end: Location,
+    },
+}
+
+impl PathSegment {
+    pub fn end(&self) -> Location {
+        match *self {
+            PathSegment::Line { end } => end,
+            PathSegment::Arc { end, .. } => end,
+        }
+    }
+}
+
+// Path of one leg of a mission, from start to the waypoint
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub waypoint: Waypoint, // Reached at the end of the path
+    pub start: Location,
+    pub segments: Vec<PathSegment>,
+}
+
+impl Path {
+    pub fn end(&self) -> Location {
+        match self.segments.last() {
+            Some(segment) => segment.end(),
+            None => self.start,
+        }
+    }
+
+    // Length flown along the path in meters, without altitude changes
+    pub fn length(&self) -> f32 {
+        let mut length = 0f32;
+        let mut from = self.start;
+        for segment in &self.segments {
+            length += match *segment {
+                PathSegment::Line { end } => self.point(&from).distance(&self.point(&end)),
+                PathSegment::Arc { radius, sweep, .. } => radius * sweep,
+            };
+            from = segment.end();
+        }
+        length
+    }
+
+    // Waypoints along the path after start, no more than spacing meters apart and ending with
+    // the end of each segment
+    // Altitude changes steadily along each segment
+    pub fn sample(&self, spacing: f32) -> Result<LinkedList<Waypoint>, PathfinderError> {
+        if spacing.is_nan() || spacing <= 0f32 || spacing.is_infinite() {
+            return Err(PathfinderError::InvalidParameter {
+                name: "spacing",
+                value: spacing,
+            });
+        }
+        let mut waypoints = LinkedList::new();
+        let mut from = self.point(&self.start);
+        for segment in &self.segments {
+            let end = self.point(&segment.end());
+            let (length, arc) = match *segment {
+                PathSegment::Line { .. } => (from.distance(&end), None),
+                PathSegment::Arc {
+                    center,
+                    radius,
+                    direction,
+                    sweep,
+                    ..
+                } => {
+                    let center = self.point(&center);
+                    let side = match direction {
+                        Direction::CounterClockwise => 1f32,
+                        Direction::Clockwise => -1f32,
+                    };
+                    let angle = (from.y - center.y).atan2(from.x - center.x);
+                    (radius * sweep, Some((center, radius, angle, side * sweep)))
+                }
+            };
+            let pieces = (length / spacing).ceil().max(1f32) as usize;
+            for k in 1..pieces {
+                let fraction = k as f32 / pieces as f32;
+                let z = from.z + (end.z - from.z) * fraction;
+                let point = match arc {
+                    Some((center, radius, angle, sweep)) => {
+                        let angle = angle + sweep * fraction;
+                        Point::new(
+                            center.x + radius * angle.cos(),
+                            center.y + radius * angle.sin(),
+                            z,
+                        )
+                    }
+                    None => Point::new(
+                        from.x + (end.x - from.x) * fraction,
+                        from.y + (end.y - from.y) * fraction,
+                        z,
+                    ),
+                };
+                let location = point.to_location(&self.start);
+                waypoints.push_back(self.waypoint.extend(location, location.alt()));
+            }
+            let location = segment.end();
+            waypoints.push_back(self.waypoint.extend(location, location.alt()));
+            from = end;
+        }
+        Ok(waypoints)
+    }
+
+    // Heading in radians counter-clockwise from east the path arrives in, none if it is empty
+    fn arrival_heading(&self) -> Option<f32> {
+        let end = self.point(&self.end());
+        match *self.segments.last()? {
+            PathSegment::Line { .. } => {
+                let before = match self.segments.len() {
+                    1 => self.start,
+                    n => self.segments[n - 2].end(),
+                };
+                let before = self.point(&before);
+                Some((end.y - before.y).atan2(end.x - before.x))
+            }
+            PathSegment::Arc {
+                center, direction, ..
+            } => {
+                let center = self.point(&center);
+                let angle = (end.y - center.y).atan2(end.x - center.x);
+                match direction {
+                    Direction::CounterClockwise => Some(angle + PI / 2f32),
+                    Direction::Clockwise => Some(angle - PI / 2f32),
+                }
+            }
+        }
+    }
+
+    // Location relative to the start of the path
+    fn point(&self, location: &Location) -> Point {
+        Point::from_location(location, &self.start)
+    }
+}
+
+impl Pathfinder {
+    // Plan a mission like try_get_adjust_path, returning each leg as the lines along tangents
+    // and arcs around obstacles and turning circles the plane flies
+    // Legs are always found with A* on the tangent graph, whichever planner is set
+    pub fn try_get_paths(
+        &mut self,
+        plane: Plane,
+        wp_list: LinkedList<Waypoint>,
+    ) -> Result<Vec<Path>, PathfinderError> {
+        if !self.initialized {
+            return Err(PathfinderError::Uninitialized);
+        }
+        let wp_list = self.prepare_waypoints(wp_list)?;
+        self.start_time = SystemTime::now();
+        self.complete = true;
+        let mut paths = Vec::new();
+        let mut leg = first_leg(plane.location, plane_heading(&plane));
+        for waypoint in wp_list {
+            leg.to = waypoint.index;
+            leg.end = waypoint.location;
+            let (start, end) = self.leg_points(&leg);
+            let mut path = match self.search(start, end, leg.heading, leg.depart) {
+                Some((overlay, end_vertex, parent)) => Path {
+                    waypoint: waypoint,
+                    start: leg.start,
+                    segments: self.generate_segments(&overlay, end_vertex, &parent),
+                },
+                None if self.timed_out() => {
+                    // return the legs solved so far as the best partial result
+                    self.complete = false;
+                    if paths.is_empty() {
+                        return Err(PathfinderError::Timeout);
+                    }
+                    return Ok(paths);
+                }
+                None => {
+                    return Err(PathfinderError::UnreachableLeg {
+                        from: leg.from,
+                        to: waypoint.index,
+                    });
+                }
+            };
+            self.assign_path_altitudes(&mut path);
+            leg = Leg {
+                from: Some(leg.to),
+                start: leg.end,
+                heading: path.arrival_heading(),
+                depart: leg.depart + path.length() / self.speed,
+                ..leg
+            };
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    // Altitudes for the ends of the segments of path as assign_altitudes gives them to points,
+    // arcs following the edge of what they go around without crossing it
+    fn assign_path_altitudes(&self, path: &mut Path) {
+        let mut prev = Point::from_location(&path.start, &self.origin);
+        let mut steps = Vec::new();
+        for segment in &path.segments {
+            let end = Point::from_location(&segment.end(), &self.origin);
+            steps.push(match *segment {
+                PathSegment::Line { .. } => (prev, end, prev.distance(&end), false),
+                PathSegment::Arc { radius, sweep, .. } => (prev, end, radius * sweep, true),
+            });
+            prev = end;
+        }
+        let lengths: Vec<f32> = steps.iter().map(|step| step.2).collect();
+        let (floors, bands) = self.leg_limits(path.start.alt(), prev.z, &steps);
+        let altitudes = self.altitudes(path.start.alt(), prev.z, &lengths, &floors, &bands);
+        for (segment, z) in path.segments.iter_mut().zip(altitudes) {
+            let end = match *segment {
+                PathSegment::Line { ref mut end } => end,
+                PathSegment::Arc { ref mut end, .. } => end,
+            };
+            *end = Location::from_radians(end.lat(), end.lon(), z);
+        }
+    }
+
+    // Lines and arcs of the path to end vertex, in the order they are flown
+    // Consecutive moves around the same node are joined into a single arc
+    fn generate_segments(
+        &self,
+        overlay: &Overlay,
+        end_vertex: VertexId,
+        parent: &[Option<VertexId>],
+    ) -> Vec<PathSegment> {
+        let mut vertices = vec![end_vertex];
+        let mut cur_vertex = end_vertex;
+        while let Some(prev) = parent[cur_vertex.0] {
+            vertices.push(prev);
+            cur_vertex = prev;
+        }
+        vertices.reverse();
+        let mut segments = Vec::new();
+        let mut arc_node = None;
+        for pair in vertices.windows(2) {
+            let (from, to) = (overlay.vertex(pair[0]), overlay.vertex(pair[1]));
+            let end = to.location.to_location(&self.origin);
+            let tangent = match from.connection {
+                Some(ref connection) => connection.neighbor == pair[1],
+                None => false,
+            };
+            if tangent {
+                if from.location.distance(&to.location) > 0.001 {
+                    segments.push(PathSegment::Line { end: end });
+                    arc_node = None;
+                }
+                continue;
+            }
+            let length = match overlay.next(pair[0]) {
+                Some((_, length)) => length,
+                None => continue,
+            };
+            if from.radius == 0f32 || length < 0.001 {
+                continue;
+            }
+            let sweep = length / from.radius;
+            if arc_node == Some(from.node) {
+                if let Some(PathSegment::Arc {
+                    sweep: ref mut total,
+                    end: ref mut arc_end,
+                    ..
+                }) = segments.last_mut()
+                {
+                    *total += sweep;
+                    *arc_end = end;
+                    continue;
+                }
+            }
+            let direction = if from.angle > 0f32 {
+                Direction::CounterClockwise
+            } else {
+                Direction::Clockwise
+            };
+            segments.push(PathSegment::Arc {
+                center: overlay.node(from.node).origin.to_location(&self.origin),
+                radius: from.radius,
+                direction: direction,
+                sweep: sweep,
+                end: end,
+            });
+            arc_node = Some(from.node);
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn sample_test() {
+        let start = Location::from_degrees(0f64, 0f64, 10f32);
+        let location = |x: f32, y: f32, z: f32| Point::new(x, y, z).to_location(&start);
+        // a quarter circle to the left around (0, 10), then north
+        let path = Path {
+            waypoint: Waypoint::new(3, location(-10f32, 30f32, 30f32), 1f32),
+            start: location(0f32, 0f32, 10f32),
+            segments: vec![
+                PathSegment::Arc {
+                    center: location(0f32, 10f32, 10f32),
+                    radius: 10f32,
+                    direction: Direction::CounterClockwise,
+                    sweep: PI / 2f32,
+                    end: location(10f32, 10f32, 20f32),
+                },
+                PathSegment::Line {
+                    end: location(10f32, 30f32, 30f32),
+                },
+            ],
+        };
+        assert!((path.length() - (5f32 * PI + 20f32)).abs() < 0.01);
+        assert!((path.arrival_heading().unwrap() - PI / 2f32).abs() < 0.001);
+        assert!(path.sample(0f32).is_err());
+        let waypoints = path.sample(5f32).unwrap();
+        assert_eq!(waypoints.len(), 4 + 4);
+        let points: Vec<Point> = waypoints
+            .iter()
+            .map(|wp| Point::from_location(&wp.location, &start))
+            .collect();
+        let center = Point::new(0f32, 10f32, 10f32);
+        for point in &points[..4] {
+            assert!((point.distance(&center) - 10f32).abs() < 0.01);
+        }
+        // climbing steadily along each segment
+        assert!((points[1].z - 15f32).abs() < 0.01);
+        assert!((points[5].z - 25f32).abs() < 0.01);
+        let mut prev = Point::new(0f32, 0f32, 10f32);
+        for point in &points {
+            assert!(prev.distance(point) <= 5.01);
+            prev = *point;
+        }
+        assert!(prev.distance(&Point::new(10f32, 30f32, 30f32)) < 0.01);
+        assert!(waypoints.iter().all(|wp| wp.index == 3));
+    }
+
+    #[test]
+    fn paths_test() {
+        let obstacle = center_obstacle(50f32, 200f32);
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![obstacle]);
+        let (plane, mut waypoints) = crossing(50f32, 50f32);
+        let plane = plane.yaw(90f32);
+        waypoints.push_back(Waypoint::new(1, at(200f32, 350f32, 50f32), 1f32));
+        let paths = pathfinder.try_get_paths(plane, waypoints.clone()).unwrap();
+        assert_eq!(paths.len(), 2);
+        let origin = pathfinder.origin;
+        let center = project(&pathfinder, &at(200f32, 200f32, 200f32));
+        // the first leg goes around the obstacle
+        assert!(paths[0].segments.iter().any(|segment| match *segment {
+            PathSegment::Arc {
+                center: c, radius, ..
+            } => Point::from_location(&c, &origin).distance(&center) < 0.01 && radius == 55f32,
+            _ => false,
+        }));
+        let mut prev = Point::from_location(&plane.location, &origin);
+        for (path, wp) in paths.iter().zip(waypoints.iter()) {
+            assert!(Point::from_location(&path.start, &origin).distance(&prev) < 0.01);
+            let points = path.sample(5f32).unwrap();
+            assert!(points.len() as f32 >= path.length() / 5f32);
+            for point in &points {
+                let point = Point::from_location(&point.location, &origin);
+                assert!(point.distance(&prev) <= 5.01);
+                // outside the obstacle grown by the default buffer
+                assert!(point.distance(&center) > 54.9);
+                prev = point;
+            }
+            assert!(prev.distance(&Point::from_location(&wp.location, &origin)) < 0.01);
+        }
+    }
+}
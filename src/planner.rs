@@ -0,0 +1,137 @@
+// Planning algorithms a pathfinder can use to find the path of each leg
+
+use std::collections::LinkedList;
+use std::fmt;
+
+use error::PathfinderError;
+use obj::Location;
+use Pathfinder;
+
+// One leg of a mission, from the plane or a waypoint to the next waypoint
+#[derive(Clone, Copy, Debug)]
+pub struct Leg {
+    pub from: Option<u32>, // Index of the waypoint left, none when leaving from the plane
+    pub to: u32,           // Index of the waypoint reached
+    pub start: Location,   // Where the leg begins
+    pub end: Location,     // Where the leg ends
+    pub heading: Option<f32>, // In radians counter-clockwise from east, none if any direction
+    pub depart: f32,       // In seconds from now
+}
+
+// Every planner finds legs within the flyzones and around the obstacles of the pathfinder
+// using it; only plan_leg is required
+pub trait Planner: Send + Sync {
+    // Short name to tell planners apart when comparing them
+    fn name(&self) -> &str;
+
+    // Get ready for the current flyzones and obstacles of pathfinder, called before planning
+    // after any of them changed; an error is returned by the planning call
+    fn prepare(&mut self, pathfinder: &Pathfinder) -> Result<(), PathfinderError> {
+        Ok(())
+    }
+
+    // Path of leg as the locations after its start, none if there is none or time ran out
+    fn plan_leg(&self, pathfinder: &Pathfinder, leg: &Leg) -> Option<LinkedList<Location>>;
+
+    // Plan leg again as the plane flies it, planners able to repair the search of the previous
+    // call for the same leg do so instead of starting over
+    fn replan(&mut self, pathfinder: &mut Pathfinder, leg: &Leg) -> Option<LinkedList<Location>> {
+        self.plan_leg(pathfinder, leg)
+    }
+}
+
+impl fmt::Debug for dyn Planner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// A* over the tangents between obstacles, the default planner
+// The pathfinder keeps the tangent graph up to date itself as obstacles change, so there is
+// nothing to prepare
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TangentGraph;
+
+impl Planner for TangentGraph {
+    fn name(&self) -> &str {
+        "tangent graph"
+    }
+
+    fn plan_leg(&self, pathfinder: &Pathfinder, leg: &Leg) -> Option<LinkedList<Location>> {
+        let (start, end) = pathfinder.leg_points(leg);
+        let path = pathfinder.adjust_path(start, end, leg.heading, leg.depart)?;
+        Some(pathfinder.to_locations(path))
+    }
+
+    // Repair the D* Lite search kept for the leg
+    fn replan(&mut self, pathfinder: &mut Pathfinder, leg: &Leg) -> Option<LinkedList<Location>> {
+        let path = pathfinder.replan_leg(leg)?;
+        Some(pathfinder.to_locations(path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+    use obj::Waypoint;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use PathfinderBuilder;
+
+    // Flies straight to each waypoint, counting how often it is prepared
+    struct Straight(Arc<AtomicUsize>);
+
+    impl Planner for Straight {
+        fn name(&self) -> &str {
+            "straight"
+        }
+
+        fn prepare(&mut self, pathfinder: &Pathfinder) -> Result<(), PathfinderError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn plan_leg(&self, pathfinder: &Pathfinder, leg: &Leg) -> Option<LinkedList<Location>> {
+            let mut path = LinkedList::new();
+            path.push_back(leg.end);
+            Some(path)
+        }
+    }
+
+    #[test]
+    fn planner_test() {
+        let prepared = Arc::new(AtomicUsize::new(0));
+        let mut pathfinder = PathfinderBuilder::new()
+            .planner(Straight(prepared.clone()))
+            .create(square_flyzone(), Vec::new())
+            .unwrap();
+        assert_eq!(pathfinder.get_planner().name(), "straight");
+        let (plane, _) = crossing(50f32, 50f32);
+        let mut waypoints = LinkedList::new();
+        for (i, x) in [150f32, 250f32, 350f32].iter().enumerate() {
+            waypoints.push_back(Waypoint::new(i as u32, at(*x, 200f32, 50f32), 1f32));
+        }
+        let path = pathfinder
+            .try_get_adjust_path(plane, waypoints.clone())
+            .unwrap();
+        let indices: Vec<u32> = path.iter().map(|wp| wp.index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        // prepared again only once the obstacles changed
+        pathfinder.set_parallel(true);
+        assert_eq!(
+            pathfinder
+                .try_get_adjust_path(plane, waypoints.clone())
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(prepared.load(Ordering::Relaxed), 1);
+        pathfinder
+            .add_obstacle(center_obstacle(20f32, 200f32))
+            .unwrap();
+        pathfinder.set_incremental(true);
+        pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+        assert_eq!(prepared.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,136 @@
+// Queries about where the plane may fly, independent of planning a path
+
+use super::*;
+
+use graph::util::{distance_to_edges, inside};
+
+impl Pathfinder {
+    // Whether the plane may be at location right now: inside a flyzone and its altitudes, outside
+    // every exclusion and outside every obstacle grown by its buffer, moving ones included
+    pub fn is_location_free(&self, location: &Location) -> bool {
+        let point = Point::from_location(location, &self.origin);
+        self.check_point(&point).is_none()
+            && !self
+                .obstacles
+                .iter()
+                .any(|obstacle| obstacle.is_moving() && self.inside_obstacle(&point, obstacle))
+    }
+
+    // Obstacle closest to location and the distance to it grown by its buffer, zero if location
+    // is inside of it, none if there are no obstacles
+    // Moving obstacles are measured from where they are now
+    pub fn nearest_obstacle(&self, location: &Location) -> Option<(ObstacleId, f32)> {
+        let point = Point::from_location(location, &self.origin);
+        self.obstacles
+            .iter()
+            .zip(&self.obstacle_ids)
+            .map(|(obstacle, &id)| (id, self.obstacle_distance(&point, obstacle)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // Horizontal distance from location to the nearest flyzone, hole or exclusion edge
+    // Positive when location is inside the area the plane may fly over, negative when it is not
+    pub fn distance_to_boundary(&self, location: &Location) -> f32 {
+        let point = Point::from_location(location, &self.origin);
+        let in_flyzone = self
+            .boundaries
+            .iter()
+            .any(|polygon| inside(&point, polygon));
+        let exclusions = self.exclusion_polygons();
+        let excluded = exclusions.iter().any(|polygon| inside(&point, polygon));
+        let distance = self
+            .boundaries
+            .iter()
+            .chain(exclusions)
+            .map(|polygon| distance_to_edges(&point, polygon))
+            .fold(f32::MAX, f32::min);
+        if in_flyzone && !excluded {
+            distance
+        } else {
+            -distance
+        }
+    }
+
+    // Distance from point to obstacle grown by its buffer, zero if point is inside of it
+    pub(crate) fn obstacle_distance(&self, point: &Point, obstacle: &Obstacle) -> f32 {
+        let buffer = self.obstacle_buffer(obstacle);
+        let mut center = Point::from_location(&obstacle.location, &self.origin);
+        let horizontal = match obstacle.shape {
+            Shape::Sphere => {
+                center.z = (obstacle.base + obstacle.height) / 2f32;
+                return (point.distance3d(&center) - obstacle.radius - buffer).max(0f32);
+            }
+            Shape::Prism(ref footprint) => {
+                let polygon: Vec<Point> = footprint
+                    .iter()
+                    .map(|location| Point::from_location(location, &self.origin))
+                    .collect();
+                if inside(point, &polygon) {
+                    0f32
+                } else {
+                    distance_to_edges(point, &polygon) - buffer
+                }
+            }
+            Shape::Cylinder => point.distance(&center) - obstacle.radius - buffer,
+        };
+        let mut vertical = point.z - obstacle.height - buffer;
+        if obstacle.is_floating() {
+            vertical = vertical.max(obstacle.base - buffer - point.z);
+        }
+        horizontal.max(0f32).hypot(vertical.max(0f32))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn query_test() {
+        let flyzone = Flyzone::new(square_flyzone().remove(0)).altitude(20f32, 300f32);
+        let tower = center_obstacle(30f32, 100f32);
+        let balloon = Obstacle::sphere(at(100f32, 300f32, 150f32), 20f32);
+        let mut pathfinder = Pathfinder::create(5f32, vec![flyzone], vec![tower, balloon]);
+        pathfinder.set_exclusions(vec![vec![
+            at(300f32, 50f32, 10f32),
+            at(350f32, 50f32, 10f32),
+            at(350f32, 100f32, 10f32),
+            at(300f32, 100f32, 10f32),
+        ]]);
+        let ids = pathfinder.get_obstacle_ids().clone();
+
+        assert!(pathfinder.is_location_free(&at(100f32, 100f32, 50f32)));
+        assert!(!pathfinder.is_location_free(&at(100f32, 100f32, 10f32)));
+        assert!(!pathfinder.is_location_free(&at(232f32, 200f32, 50f32)));
+        assert!(!pathfinder.is_location_free(&at(320f32, 75f32, 50f32)));
+        assert!(!pathfinder.is_location_free(&at(450f32, 200f32, 50f32)));
+        assert!(pathfinder.is_location_free(&at(100f32, 300f32, 100f32)));
+
+        let (id, distance) = pathfinder
+            .nearest_obstacle(&at(250f32, 200f32, 50f32))
+            .unwrap();
+        assert_eq!(id, ids[0]);
+        assert!((distance - 15f32).abs() < 0.01);
+        let (_, distance) = pathfinder
+            .nearest_obstacle(&at(200f32, 200f32, 120f32))
+            .unwrap();
+        assert!((distance - 15f32).abs() < 0.01);
+        let (id, distance) = pathfinder
+            .nearest_obstacle(&at(100f32, 300f32, 100f32))
+            .unwrap();
+        assert_eq!(id, ids[1]);
+        assert!((distance - 25f32).abs() < 0.01);
+        let (_, distance) = pathfinder
+            .nearest_obstacle(&at(210f32, 200f32, 50f32))
+            .unwrap();
+        assert_eq!(distance, 0f32);
+
+        let distance = pathfinder.distance_to_boundary(&at(100f32, 30f32, 50f32));
+        assert!((distance - 30f32).abs() < 0.01);
+        let distance = pathfinder.distance_to_boundary(&at(320f32, 75f32, 50f32));
+        assert!((distance + 20f32).abs() < 0.01);
+        let distance = pathfinder.distance_to_boundary(&at(450f32, 200f32, 50f32));
+        assert!((distance + 50f32).abs() < 0.01);
+    }
+}
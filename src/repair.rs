@@ -0,0 +1,347 @@
+// Checks of requested waypoints against the flyzones and obstacles, and repair of the ones the
+// plane cannot reach by moving them to the nearest location it can
+
+use super::*;
+
+use graph::util::{distance_to_edges, inside};
+use std::fmt;
+
+const REPAIR_STEP: f32 = 1f32; // In meters, between the rings of locations tried
+const MAX_REPAIR_DISTANCE: f32 = 250f32; // In meters
+
+// Reason the plane cannot reach a waypoint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaypointProblem {
+    // Outside every flyzone
+    OutsideFlyzone,
+    // Inside an exclusion polygon or a hole of a flyzone
+    InExclusion,
+    // Below the floor or above the ceiling of the flyzones around it
+    OutsideAltitudes,
+    // Inside an obstacle grown by its buffer
+    InObstacle(ObstacleId),
+}
+
+impl fmt::Display for WaypointProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WaypointProblem::OutsideFlyzone => write!(f, "outside the flyzones"),
+            WaypointProblem::InExclusion => write!(f, "inside an exclusion"),
+            WaypointProblem::OutsideAltitudes => write!(f, "outside the flyzone altitudes"),
+            WaypointProblem::InObstacle(id) => write!(f, "inside obstacle {:?}", id),
+        }
+    }
+}
+
+impl Pathfinder {
+    // Index and problem of every waypoint the plane cannot reach
+    pub fn check_waypoints(&self, wp_list: &LinkedList<Waypoint>) -> Vec<(u32, WaypointProblem)> {
+        wp_list
+            .iter()
+            .filter_map(|waypoint| {
+                let point = Point::from_location(&waypoint.location, &self.origin);
+                self.check_point(&point)
+                    .map(|problem| (waypoint.index, problem))
+            })
+            .collect()
+    }
+
+    // Move waypoints that cannot be reached to the nearest location that can, marking them as
+    // moved, instead of reporting them as errors
+    pub fn set_repair_waypoints(&mut self, repair: bool) {
+        self.repair_waypoints = repair;
+    }
+
+    // Waypoints to plan for, repaired if enabled
+    // Fails on the first waypoint that cannot be reached or repaired, or when repairing takes
+    // longer than max_process_time
+    pub(crate) fn prepare_waypoints(
+        &self,
+        wp_list: LinkedList<Waypoint>,
+    ) -> Result<LinkedList<Waypoint>, PathfinderError> {
+        let started = SystemTime::now();
+        let mut waypoints = LinkedList::new();
+        for waypoint in wp_list {
+            let point = Point::from_location(&waypoint.location, &self.origin);
+            let problem = match self.check_point(&point) {
+                Some(problem) => problem,
+                None => {
+                    waypoints.push_back(waypoint);
+                    continue;
+                }
+            };
+            let repaired = if self.repair_waypoints {
+                self.repair_point(&point, &started)
+            } else {
+                None
+            };
+            if repaired.is_none() && out_of_time(&started, self.max_process_time) {
+                return Err(PathfinderError::Timeout);
+            }
+            match (repaired, problem) {
+                (Some(point), _) => {
+                    let location = point.to_location(&self.origin);
+                    let mut waypoint = waypoint.extend(location, location.alt());
+                    waypoint.moved = true;
+                    waypoints.push_back(waypoint);
+                }
+                (None, WaypointProblem::OutsideAltitudes) => {
+                    return Err(PathfinderError::WaypointAltitude {
+                        waypoint: waypoint.index,
+                        alt: point.z,
+                    });
+                }
+                (None, problem) => {
+                    return Err(PathfinderError::InvalidWaypoint {
+                        waypoint: waypoint.index,
+                        problem: problem,
+                    });
+                }
+            }
+        }
+        Ok(waypoints)
+    }
+
+    // First reason the plane cannot reach point, none if it can
+    pub(crate) fn check_point(&self, point: &Point) -> Option<WaypointProblem> {
+        if !self
+            .boundaries
+            .iter()
+            .any(|boundary| inside(point, boundary))
+        {
+            return Some(WaypointProblem::OutsideFlyzone);
+        }
+        if self
+            .exclusion_polygons()
+            .iter()
+            .any(|polygon| inside(point, polygon))
+        {
+            return Some(WaypointProblem::InExclusion);
+        }
+        let (min_alt, max_alt) = self.altitude_band(point);
+        if point.z < min_alt || point.z > max_alt {
+            return Some(WaypointProblem::OutsideAltitudes);
+        }
+        self.obstacles
+            .iter()
+            .zip(&self.obstacle_ids)
+            .filter(|&(obstacle, _)| !obstacle.is_moving())
+            .find(|&(obstacle, _)| self.inside_obstacle(point, obstacle))
+            .map(|(_, &id)| WaypointProblem::InObstacle(id))
+    }
+
+    // Whether point is inside obstacle grown by its buffer
+    pub(crate) fn inside_obstacle(&self, point: &Point, obstacle: &Obstacle) -> bool {
+        if obstacle.is_floating() {
+            return self.floating_intersect(point, point, obstacle);
+        }
+        let buffer = self.obstacle_buffer(obstacle);
+        if point.z >= obstacle.height + buffer {
+            return false;
+        }
+        match obstacle.shape {
+            Shape::Prism(ref footprint) => {
+                let polygon: Vec<Point> = footprint
+                    .iter()
+                    .map(|location| Point::from_location(location, &self.origin))
+                    .collect();
+                inside(point, &polygon) || distance_to_edges(point, &polygon) < buffer
+            }
+            _ => {
+                let center = Point::from_location(&obstacle.location, &self.origin);
+                point.distance(&center) < obstacle.radius + buffer
+            }
+        }
+    }
+
+    // Whether the plane can turn around point within its turning radius, whichever way it
+    // arrives, without crossing an edge or entering the buffer of an obstacle
+    fn room_to_turn(&self, point: &Point) -> bool {
+        let edges = self
+            .boundaries
+            .iter()
+            .chain(self.exclusion_polygons())
+            .map(|polygon| distance_to_edges(point, polygon))
+            .fold(f32::MAX, f32::min);
+        edges >= self.turning_radius
+            && self
+                .obstacles
+                .iter()
+                .filter(|obstacle| !obstacle.is_moving())
+                .all(|obstacle| self.obstacle_distance(point, obstacle) >= self.turning_radius)
+    }
+
+    // Nearest location to point the plane can reach and turn around, at the nearest altitude it
+    // is allowed at
+    // Locations are tried on rings around point, after stepping into the flyzones or out of an
+    // exclusion if it is outside or inside of one
+    // Gives up once max_process_time has passed since started
+    fn repair_point(&self, point: &Point, started: &SystemTime) -> Option<Point> {
+        let mut start = *point;
+        match self.check_point(&start) {
+            Some(WaypointProblem::OutsideFlyzone) => {
+                start = step_across(&start, &self.boundaries);
+            }
+            Some(WaypointProblem::InExclusion) => {
+                let exclusions: Vec<Vec<Point>> = self
+                    .exclusion_polygons()
+                    .iter()
+                    .filter(|polygon| inside(&start, polygon))
+                    .cloned()
+                    .collect();
+                start = step_across(&start, &exclusions);
+            }
+            _ => {}
+        }
+        let rings = (MAX_REPAIR_DISTANCE / REPAIR_STEP) as usize;
+        for ring in 0..=rings {
+            if out_of_time(started, self.max_process_time) {
+                return None;
+            }
+            let radius = ring as f32 * REPAIR_STEP;
+            let count = ((2f32 * PI * radius / REPAIR_STEP).ceil() as usize).max(1);
+            let mut best: Option<Point> = None;
+            for k in 0..count {
+                let angle = 2f32 * PI * k as f32 / count as f32;
+                let mut candidate = Point::new(
+                    start.x + radius * angle.cos(),
+                    start.y + radius * angle.sin(),
+                    point.z,
+                );
+                let (min_alt, max_alt) = self.altitude_band(&candidate);
+                candidate.z = candidate.z.max(min_alt).min(max_alt);
+                if self.check_point(&candidate).is_some() || !self.room_to_turn(&candidate) {
+                    continue;
+                }
+                let nearer = match best {
+                    Some(ref best) => candidate.distance3d(point) < best.distance3d(point),
+                    None => true,
+                };
+                if nearer {
+                    best = Some(candidate);
+                }
+            }
+            if best.is_some() {
+                return best;
+            }
+        }
+        None
+    }
+}
+
+fn out_of_time(started: &SystemTime, max_process_time: Duration) -> bool {
+    match started.elapsed() {
+        Ok(elapsed) => elapsed >= max_process_time,
+        Err(_) => false,
+    }
+}
+
+// Point a step past the nearest edge of polygons, going from point across it
+fn step_across(point: &Point, polygons: &[Vec<Point>]) -> Point {
+    let mut nearest: Option<(f32, f32, f32)> = None;
+    for polygon in polygons {
+        for i in 0..polygon.len() {
+            let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+            let (x, y, dist_squared, _) = intersect_distance(a, b, point);
+            let closer = match nearest {
+                Some((_, _, best)) => dist_squared < best,
+                None => true,
+            };
+            if closer {
+                nearest = Some((x, y, dist_squared));
+            }
+        }
+    }
+    match nearest {
+        Some((x, y, dist_squared)) if dist_squared > 0f32 => {
+            let dist = dist_squared.sqrt();
+            let scale = (dist + REPAIR_STEP) / dist;
+            Point::new(
+                point.x + (x - point.x) * scale,
+                point.y + (y - point.y) * scale,
+                point.z,
+            )
+        }
+        _ => *point,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+
+    #[test]
+    fn repair_test() {
+        let obstacle = center_obstacle(30f32, 100f32);
+        let mut pathfinder = Pathfinder::create(1f32, square_flyzone(), vec![obstacle]);
+        pathfinder.set_exclusions(vec![vec![
+            at(300f32, 50f32, 10f32),
+            at(350f32, 50f32, 10f32),
+            at(350f32, 100f32, 10f32),
+            at(300f32, 100f32, 10f32),
+        ]]);
+        let id = pathfinder.get_obstacle_ids()[0];
+        let mut waypoints = LinkedList::new();
+        for (x, y, z) in [
+            (100f32, 100f32, 50f32),
+            (210f32, 200f32, 50f32),
+            (450f32, 200f32, 50f32),
+            (310f32, 75f32, 50f32),
+            (200f32, 200f32, 150f32),
+        ] {
+            let index = waypoints.len() as u32;
+            waypoints.push_back(Waypoint::new(index, at(x, y, z), 1f32));
+        }
+        assert_eq!(
+            pathfinder.check_waypoints(&waypoints),
+            vec![
+                (1, WaypointProblem::InObstacle(id)),
+                (2, WaypointProblem::OutsideFlyzone),
+                (3, WaypointProblem::InExclusion),
+            ]
+        );
+        let plane = Plane::new(at(50f32, 50f32, 50f32));
+        assert_eq!(
+            pathfinder
+                .try_get_adjust_path(plane, waypoints.clone())
+                .err(),
+            Some(PathfinderError::InvalidWaypoint {
+                waypoint: 1,
+                problem: WaypointProblem::InObstacle(id)
+            })
+        );
+
+        pathfinder.set_repair_waypoints(true);
+        let repaired: Vec<Waypoint> = pathfinder
+            .prepare_waypoints(waypoints.clone())
+            .unwrap()
+            .into_iter()
+            .collect();
+        let point = |i: usize| Point::from_location(&repaired[i].location, &origin());
+        let moved: Vec<bool> = repaired.iter().map(|wp| wp.moved).collect();
+        assert_eq!(moved, vec![false, true, true, true, false]);
+        // a turning radius outside the obstacle grown by the buffer, on the side it was closest to
+        let center = Point::new(200f32, 200f32, 0f32);
+        assert!(point(1).distance(&center) >= 40f32 && point(1).distance(&center) < 41.5);
+        assert!(point(1).x > 200f32);
+        assert!(point(2).x <= 395f32 && point(2).x > 393f32);
+        assert!(point(3).x <= 295f32 && point(3).x > 293f32);
+        for i in 1..4 {
+            assert!((point(i).z - 50f32).abs() < 0.01);
+        }
+        let repaired: LinkedList<Waypoint> = repaired.into_iter().collect();
+        assert!(pathfinder.check_waypoints(&repaired).is_empty());
+        let path = pathfinder
+            .try_get_adjust_path(plane, waypoints.clone())
+            .unwrap();
+        assert!(path.iter().any(|wp| wp.moved));
+
+        // out of time before any ring is tried
+        pathfinder.set_process_time(0);
+        assert_eq!(
+            pathfinder.prepare_waypoints(waypoints).err(),
+            Some(PathfinderError::Timeout)
+        );
+    }
+}
@@ -0,0 +1,459 @@
+// Incremental replanning of a leg with D* Lite
+// The search runs backwards from the destination and is kept between calls, so when the
+// plane moves or obstacles change only the vertices affected are searched again
+// Whether the plane can climb over an obstacle depends on how far it flew from the start,
+// which a backward search does not know, so tangents flying over obstacles are left out
+
+use super::*;
+use graph::Vertex;
+
+// Priority of a vertex, ordered by the first value then the second
+type Key = (OrderedFloat<f32>, OrderedFloat<f32>);
+
+pub struct Replanner {
+    pub goal: NodeId, // Destination, added to the graph for as long as the search is kept
+    end: Point,       // Location of the destination
+    line: (Point, Point), // Leg as first planned, reference for direct_path_cost
+    g: Vec<f32>,      // Cost to the destination by vertex, as last expanded
+    rhs: Vec<f32>,    // Cost to the destination by vertex, from its successors
+    incoming: Vec<Option<VertexId>>, // Departure of the tangent arriving at each vertex
+    queue: BinaryHeap<(Reverse<Key>, VertexId)>, // Inconsistent vertices, may hold stale keys
+    km: f32,          // Distance the start moved since the search began
+    last: Point,      // Start of the previous call
+    changes: usize,   // Graph changes taken into account
+}
+
+impl Replanner {
+    // Add the destination to the graph and begin searching from it
+    pub fn new(pathfinder: &mut Pathfinder, start: Point, end: Point) -> Self {
+        let changes = pathfinder.graph.changes.len();
+        let goal = pathfinder.insert_goal_node(end);
+        Replanner {
+            goal: goal,
+            end: end,
+            line: (start, end),
+            g: Vec::new(),
+            rhs: Vec::new(),
+            incoming: Vec::new(),
+            queue: BinaryHeap::new(),
+            km: 0f32,
+            last: start,
+            changes: changes,
+        }
+    }
+
+    pub fn leads_to(&self, end: &Point) -> bool {
+        self.end.distance(end) == 0f32 && self.end.z == end.z
+    }
+
+    // Find a path from start to the destination, reusing the search of the previous call
+    // Returns none if there is no path or time ran out
+    pub fn plan(
+        &mut self,
+        pathfinder: &Pathfinder,
+        start: Point,
+        heading: Option<f32>,
+    ) -> Option<LinkedList<Point>> {
+        self.km += self.last.distance(&start);
+        self.last = start;
+        self.sync(pathfinder, &start);
+
+        let graph = &pathfinder.graph;
+        let mut overlay = Overlay::new(graph);
+        let start_vertices = pathfinder.add_start(&mut overlay, start, heading);
+        let start_nodes: Vec<NodeId> = start_vertices
+            .iter()
+            .map(|v| overlay.vertex(*v).node)
+            .collect();
+        for &start_node in &start_nodes {
+            for i in 0..graph.nodes.len() {
+                let node = NodeId(i);
+                if !graph.node(node).goal || node == self.goal {
+                    pathfinder.connect(&mut overlay, start_node, node);
+                }
+            }
+        }
+
+        let (mut parent, entries, finish) =
+            self.leave_start(pathfinder, &overlay, &start_vertices, &start_nodes);
+        let finish_cost = finish.map(|(_, cost)| cost).unwrap_or(f32::INFINITY);
+        let best = self.compute(pathfinder, &start, &entries, finish_cost)?;
+        if best == f32::INFINITY {
+            return None;
+        }
+        let mut end_vertex = match finish {
+            Some((vertex, cost)) if cost <= best => vertex,
+            _ => {
+                let (entry, _) = entries
+                    .iter()
+                    .min_by_key(|&&(v, cost)| OrderedFloat(cost + self.g[v.0]))?;
+                *entry
+            }
+        };
+        // follow the cheapest successors down to the destination
+        for _ in 0..graph.vertices.len() {
+            if overlay.vertex(end_vertex).node == self.goal {
+                break;
+            }
+            let (next, _) = self
+                .successors(pathfinder, end_vertex)
+                .into_iter()
+                .min_by_key(|&(next, cost)| OrderedFloat(cost + self.g[next.0]))?;
+            parent[next.0] = Some(end_vertex);
+            end_vertex = next;
+        }
+        Some(pathfinder.generate_path(&overlay, end_vertex, &parent, &start))
+    }
+
+    // Take the vertices added or changed in the graph since the last call into account
+    fn sync(&mut self, pathfinder: &Pathfinder, start: &Point) {
+        let graph = &pathfinder.graph;
+        let known = self.g.len();
+        let size = graph.vertices.len();
+        self.g.resize(size, f32::INFINITY);
+        self.rhs.resize(size, f32::INFINITY);
+        self.incoming.resize(size, None);
+        for i in known..size {
+            if let Some(ref connection) = graph.vertices[i].connection {
+                self.incoming[connection.neighbor.0] = Some(VertexId(i));
+            }
+        }
+        // slots freed by the graph may have been reused by new tangents
+        for &id in &graph.changes[self.changes..] {
+            if let Some(ref connection) = graph.vertex(id).connection {
+                self.incoming[connection.neighbor.0] = Some(id);
+            }
+        }
+        for i in self.changes..graph.changes.len() {
+            self.update_vertex(pathfinder, graph.changes[i], start);
+        }
+        self.changes = graph.changes.len();
+    }
+
+    // Graph changes taken into account, the ones before are no longer needed by this search
+    pub fn changes_seen(&self) -> usize {
+        self.changes
+    }
+
+    // The first count graph changes were dropped
+    pub fn forget_changes(&mut self, count: usize) {
+        self.changes -= count;
+    }
+
+    // Cheapest ways from the start vertices onto the graph, through the tangents and turning
+    // circles added for the start
+    // Returns the parent of each overlay vertex on the way, the graph vertices reached with
+    // their cost, and the destination if it can be reached directly
+    #[allow(clippy::type_complexity)]
+    fn leave_start(
+        &self,
+        pathfinder: &Pathfinder,
+        overlay: &Overlay,
+        start_vertices: &[VertexId],
+        start_nodes: &[NodeId],
+    ) -> (
+        Vec<Option<VertexId>>,
+        Vec<(VertexId, f32)>,
+        Option<(VertexId, f32)>,
+    ) {
+        let size = overlay.num_vertices();
+        let mut cost = vec![f32::INFINITY; size];
+        let mut parent = vec![None; size];
+        let mut entries = Vec::new();
+        let mut open_list = BinaryHeap::new();
+        for &v in start_vertices {
+            cost[v.0] = 0f32;
+            open_list.push((Reverse(OrderedFloat(0f32)), v));
+        }
+        while let Some((Reverse(OrderedFloat(c)), cur)) = open_list.pop() {
+            if c > cost[cur.0] {
+                continue;
+            }
+            let vertex = overlay.vertex(cur);
+            if vertex.node == self.goal {
+                return (parent, entries, Some((cur, c)));
+            }
+            if cur.0 < self.g.len() {
+                entries.push((cur, c));
+                continue;
+            }
+            let mut neighbors = Vec::new();
+            if let Some(ref connection) = vertex.connection {
+                if connection.threshold == 0f32 {
+                    neighbors.push((connection.neighbor, connection.distance));
+                }
+            }
+            if let Some((next, arc)) = overlay.next(cur) {
+                // turning on a start circle moves the plane away from its heading
+                let on_start = start_nodes.contains(&vertex.node) && vertex.radius > 0f32;
+                let turn = if on_start {
+                    pathfinder.config.heading_modifier_weight * arc / vertex.radius
+                } else {
+                    0f32
+                };
+                if !on_start || pathfinder.start_turn_clear(overlay, cur, next) {
+                    neighbors.push((next, arc + turn));
+                }
+            }
+            for (next, step) in neighbors {
+                let next_vertex = overlay.vertex(next);
+                if !self.enterable(overlay.node(next_vertex.node), next_vertex) {
+                    continue;
+                }
+                let new_cost = c + step + self.direct_path_cost(pathfinder, next_vertex);
+                if new_cost < cost[next.0] {
+                    cost[next.0] = new_cost;
+                    parent[next.0] = Some(cur);
+                    open_list.push((Reverse(OrderedFloat(new_cost)), next));
+                }
+            }
+        }
+        (parent, entries, None)
+    }
+
+    // Expand vertices until the cheapest way from the start through entries is known
+    // Returns its cost, or none if time ran out
+    fn compute(
+        &mut self,
+        pathfinder: &Pathfinder,
+        start: &Point,
+        entries: &[(VertexId, f32)],
+        finish: f32,
+    ) -> Option<f32> {
+        let best_of = |g: &[f32]| {
+            entries
+                .iter()
+                .map(|&(v, cost)| cost + g[v.0])
+                .fold(finish, f32::min)
+        };
+        let mut best = best_of(&self.g);
+        while let Some(&(Reverse(key), u)) = self.queue.peek() {
+            // the start is consistent once no vertex left could lead to a cheaper path
+            if key >= (OrderedFloat(best + self.km), OrderedFloat(best)) {
+                break;
+            }
+            if pathfinder.timed_out() {
+                return None;
+            }
+            self.queue.pop();
+            if self.g[u.0] == self.rhs[u.0] {
+                continue;
+            }
+            let new_key = self.key(pathfinder, u, start);
+            if key < new_key {
+                self.queue.push((Reverse(new_key), u));
+                continue;
+            }
+            let vertex = pathfinder.graph.vertex(u);
+            let location = vertex.location.to_location(&pathfinder.origin);
+            pathfinder.observer.node_expanded(&location, self.rhs[u.0]);
+            if self.g[u.0] > self.rhs[u.0] {
+                self.g[u.0] = self.rhs[u.0];
+            } else {
+                self.g[u.0] = f32::INFINITY;
+                self.update_vertex(pathfinder, u, start);
+            }
+            for p in self.predecessors(&pathfinder.graph, u) {
+                self.update_vertex(pathfinder, p, start);
+            }
+            if entries.iter().any(|&(v, _)| v == u) {
+                best = best_of(&self.g);
+            }
+        }
+        Some(best)
+    }
+
+    // Recompute the cost of id from its successors and queue it if it changed
+    fn update_vertex(&mut self, pathfinder: &Pathfinder, id: VertexId, start: &Point) {
+        let vertex = pathfinder.graph.vertex(id);
+        self.rhs[id.0] = if vertex.node == self.goal && !vertex.sentinel {
+            0f32
+        } else {
+            self.successors(pathfinder, id)
+                .into_iter()
+                .map(|(next, cost)| cost + self.g[next.0])
+                .fold(f32::INFINITY, f32::min)
+        };
+        if self.g[id.0] != self.rhs[id.0] {
+            let key = self.key(pathfinder, id, start);
+            self.queue.push((Reverse(key), id));
+        }
+    }
+
+    fn key(&self, pathfinder: &Pathfinder, id: VertexId, start: &Point) -> Key {
+        let cost = self.g[id.0].min(self.rhs[id.0]);
+        let h = start.distance(&pathfinder.graph.vertex(id).location);
+        (OrderedFloat(cost + h + self.km), OrderedFloat(cost))
+    }
+
+    // Vertices reached from id along a tangent or its ring, with the cost to get there
+    fn successors(&self, pathfinder: &Pathfinder, id: VertexId) -> Vec<(VertexId, f32)> {
+        let graph = &pathfinder.graph;
+        let mut moves = Vec::new();
+        if let Some(ref connection) = graph.vertex(id).connection {
+            if connection.threshold == 0f32 {
+                moves.push((connection.neighbor, connection.distance));
+            }
+        }
+        if let Some(next) = graph.next(id) {
+            moves.push(next);
+        }
+        moves
+            .into_iter()
+            .filter_map(|(next, step)| {
+                let vertex = graph.vertex(next);
+                if self.enterable(graph.node(vertex.node), vertex) {
+                    Some((next, step + self.direct_path_cost(pathfinder, vertex)))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Vertices that may lead to id; the tangent is only a candidate, it may have been removed
+    fn predecessors(&self, graph: &Graph, id: VertexId) -> Vec<VertexId> {
+        let mut predecessors: Vec<VertexId> = graph.prev_vertex(id).into_iter().collect();
+        predecessors.extend(self.incoming[id.0]);
+        predecessors
+    }
+
+    // Sentinels and the destinations of other legs cannot be entered
+    fn enterable(&self, node: &Node, vertex: &Vertex) -> bool {
+        !vertex.sentinel && !node.removed && (!node.goal || vertex.node == self.goal)
+    }
+
+    fn direct_path_cost(&self, pathfinder: &Pathfinder, vertex: &Vertex) -> f32 {
+        pathfinder.direct_path_cost(&self.line.0, &self.line.1, &vertex.location)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixture::*;
+    use path_length;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    impl PlannerObserver for Arc<AtomicUsize> {
+        fn node_expanded(&self, location: &Location, cost: f32) {
+            self.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn incremental_test() {
+        let obstacle = |x: f32, y: f32| Obstacle::new(at(x, y, 200f32), 50f32, 200f32);
+        let create = |incremental: bool| {
+            PathfinderBuilder::new()
+                .incremental(incremental)
+                .config(PathfinderConfig {
+                    direct_path_modifier_weight: 0f32,
+                    heading_modifier_weight: 0f32,
+                })
+                .create(square_flyzone(), vec![obstacle(200f32, 210f32)])
+                .unwrap()
+        };
+        // length of the path from the plane at x
+        let plan = |pathfinder: &mut Pathfinder, x: f32| -> f32 {
+            let (_, waypoints) = crossing(50f32, 50f32);
+            let plane = Plane::new(at(x, 200f32, 50f32));
+            let start = project(pathfinder, &plane.location);
+            let origin = pathfinder.origin;
+            let path = pathfinder.try_get_adjust_path(plane, waypoints).unwrap();
+            let points = path
+                .iter()
+                .map(|wp| Point::from_location(&wp.location, &origin))
+                .collect();
+            path_length(&start, &points)
+        };
+        // equally short paths may go either way around
+        let same = |a: f32, b: f32| (a - b).abs() < 0.01;
+        let expansions = Arc::new(AtomicUsize::new(0));
+        let mut replanned = create(true);
+        replanned.set_observer(expansions.clone());
+        let mut fresh = create(false);
+
+        let length = plan(&mut replanned, 50f32);
+        assert!(length > 301f32);
+        assert!(same(length, plan(&mut fresh, 50f32)));
+        let first = expansions.swap(0, Ordering::Relaxed);
+        // the plane moves along the leg
+        assert!(same(plan(&mut replanned, 60f32), plan(&mut fresh, 60f32)));
+        assert!(expansions.load(Ordering::Relaxed) < first);
+        assert!(same(plan(&mut replanned, 80f32), plan(&mut fresh, 80f32)));
+
+        // obstacles change under the kept search
+        let id = replanned.add_obstacle(obstacle(290f32, 170f32)).unwrap();
+        fresh.add_obstacle(obstacle(290f32, 170f32)).unwrap();
+        assert!(same(plan(&mut replanned, 80f32), plan(&mut fresh, 80f32)));
+        let first_id = replanned.get_obstacle_ids()[0];
+        replanned.remove_obstacle(first_id).unwrap();
+        let first_id = fresh.get_obstacle_ids()[0];
+        fresh.remove_obstacle(first_id).unwrap();
+        assert!(same(plan(&mut replanned, 80f32), plan(&mut fresh, 80f32)));
+        replanned.remove_obstacle(id).unwrap();
+        assert!(same(plan(&mut replanned, 80f32), 270f32));
+    }
+
+    #[test]
+    fn flyover_test() {
+        // a corridor blocked by an obstacle the plane can only fly over
+        let corridor = vec![vec![
+            at(0f32, 150f32, 10f32),
+            at(0f32, 250f32, 10f32),
+            at(400f32, 250f32, 10f32),
+            at(400f32, 150f32, 10f32),
+        ]];
+        let plan = |incremental: bool, height: f32| {
+            let obstacle = Obstacle::new(at(200f32, 200f32, height), 60f32, height);
+            let mut pathfinder = PathfinderBuilder::new()
+                .incremental(incremental)
+                .create(corridor.clone(), vec![obstacle])
+                .unwrap();
+            let (plane, waypoints) = crossing(100f32, 100f32);
+            let path: Option<Vec<Point>> = pathfinder
+                .try_get_adjust_path(plane, waypoints)
+                .map(|path| {
+                    path.iter()
+                        .map(|wp| Point::from_location(&wp.location, &origin()))
+                        .collect()
+                })
+                .ok();
+            path
+        };
+        // low enough to climb over, the kept search has no path and the leg is searched again
+        let fresh = plan(false, 20f32).unwrap();
+        let replanned = plan(true, 20f32).unwrap();
+        assert_eq!(fresh.len(), replanned.len());
+        for (a, b) in fresh.iter().zip(&replanned) {
+            assert!(a.distance3d(b) < 0.01);
+        }
+        // too high to climb over
+        assert!(plan(false, 400f32).is_none());
+        assert!(plan(true, 400f32).is_none());
+    }
+
+    #[test]
+    fn around_test() {
+        // flying over is the shorter way, but the kept search goes around the obstacle
+        let create = |incremental: bool| {
+            PathfinderBuilder::new()
+                .incremental(incremental)
+                .create(square_flyzone(), vec![center_obstacle(50f32, 20f32)])
+                .unwrap()
+        };
+        assert_eq!(cross_square(&mut create(false), 50f32).len(), 2);
+        let mut pathfinder = create(true);
+        let points = cross_square(&mut pathfinder, 50f32);
+        assert!(points.len() > 2);
+        let center = project(&pathfinder, &at(200f32, 200f32, 0f32));
+        let on_edge = |p: &Point| (p.distance(&center) - 50f32).abs() < 0.01;
+        for pair in points.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let (_, _, dist, _) = intersect_distance(a, b, &center);
+            assert!(dist.sqrt() > 49.9 || (on_edge(a) && on_edge(b)));
+        }
+    }
+}